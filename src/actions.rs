@@ -0,0 +1,405 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    // Also flies up and down with the fly camera
+    Jump,
+    Crouch,
+    Sprint,
+    ThrowCube,
+    ThrowBall,
+    ToggleCamera,
+    GrabCursor,
+    Pause,
+    NewMaze,
+    ToggleSolution,
+    ToggleHeatMap,
+    ExportMaze,
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Jump,
+        Action::Crouch,
+        Action::Sprint,
+        Action::ThrowCube,
+        Action::ThrowBall,
+        Action::ToggleCamera,
+        Action::GrabCursor,
+        Action::Pause,
+        Action::NewMaze,
+        Action::ToggleSolution,
+        Action::ToggleHeatMap,
+        Action::ExportMaze,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Dvorak,
+}
+
+impl KeyboardLayout {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyboardLayout::Qwerty => "QWERTY",
+            KeyboardLayout::Dvorak => "Dvorak",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            KeyboardLayout::Qwerty => KeyboardLayout::Dvorak,
+            KeyboardLayout::Dvorak => KeyboardLayout::Qwerty,
+        }
+    }
+
+    // KeyCode follows the layout, so the Dvorak preset puts WASD on the same physical keys
+    fn keys(&self, action: Action) -> Vec<KeyCode> {
+        let qwerty = *self == KeyboardLayout::Qwerty;
+        let key = match action {
+            Action::MoveForward if qwerty => KeyCode::W,
+            Action::MoveForward => KeyCode::Comma,
+            Action::MoveBackward if qwerty => KeyCode::S,
+            Action::MoveBackward => KeyCode::O,
+            Action::StrafeLeft => KeyCode::A,
+            Action::StrafeRight if qwerty => KeyCode::D,
+            Action::StrafeRight => KeyCode::E,
+            Action::Jump => KeyCode::Space,
+            Action::Crouch => KeyCode::ShiftLeft,
+            Action::Sprint => KeyCode::ControlLeft,
+            Action::ThrowCube if qwerty => KeyCode::E,
+            Action::ThrowCube => KeyCode::Period,
+            Action::ThrowBall if qwerty => KeyCode::Q,
+            Action::ThrowBall => KeyCode::Semicolon,
+            Action::ToggleCamera if qwerty => KeyCode::V,
+            Action::ToggleCamera => KeyCode::P,
+            Action::GrabCursor => KeyCode::Tab,
+            Action::Pause => KeyCode::Escape,
+            Action::NewMaze => KeyCode::N,
+            Action::ToggleSolution => KeyCode::H,
+            Action::ToggleHeatMap => KeyCode::G,
+            Action::ExportMaze => KeyCode::F2,
+        };
+        vec![key]
+    }
+}
+
+// Gamepad buttons do not depend on the keyboard layout. Movement is on the left stick as well.
+fn default_buttons(action: Action) -> Vec<GamepadButtonType> {
+    match action {
+        Action::MoveForward => vec![GamepadButtonType::DPadUp],
+        Action::MoveBackward => vec![GamepadButtonType::DPadDown],
+        Action::StrafeLeft => vec![GamepadButtonType::DPadLeft],
+        Action::StrafeRight => vec![GamepadButtonType::DPadRight],
+        Action::Jump => vec![GamepadButtonType::South],
+        Action::Crouch => vec![GamepadButtonType::East],
+        Action::Sprint => vec![GamepadButtonType::LeftThumb],
+        Action::ThrowCube => vec![GamepadButtonType::RightTrigger2],
+        Action::ThrowBall => vec![GamepadButtonType::LeftTrigger2],
+        Action::ToggleCamera => vec![GamepadButtonType::North],
+        Action::Pause => vec![GamepadButtonType::Start],
+        Action::NewMaze => vec![GamepadButtonType::Select],
+        Action::GrabCursor
+        | Action::ToggleSolution
+        | Action::ToggleHeatMap
+        | Action::ExportMaze => vec![],
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct GamepadConfig {
+    // Stick deflection ignored around the center, from 0.0 to 1.0
+    pub dead_zone: f32,
+    // Degrees turned per second with the right stick fully deflected
+    pub look_sensitivity: f32,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        GamepadConfig {
+            dead_zone: 0.15,
+            look_sensitivity: 180.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct LookConfig {
+    // Degrees turned per count of mouse movement
+    pub sensitivity: f32,
+    // Seconds the view takes to catch up with the mouse, 0.0 turns smoothing off
+    pub smoothing: f32,
+    pub invert_y: bool,
+    // Lowest and highest pitch in degrees
+    pub min_pitch: f32,
+    pub max_pitch: f32,
+}
+
+impl Default for LookConfig {
+    fn default() -> Self {
+        LookConfig {
+            sensitivity: 0.08,
+            smoothing: 0.0,
+            invert_y: false,
+            min_pitch: -88.0,
+            max_pitch: 88.0,
+        }
+    }
+}
+
+// Contents of <config dir>/maze/bindings.ron. Keys and buttons listed for an action replace the
+// preset ones:
+//
+// (
+//     layout: Dvorak,
+//     keys: {
+//         ThrowBall: [Z],
+//     },
+//     buttons: {
+//         Jump: [RightTrigger],
+//     },
+//     gamepad: (
+//         dead_zone: 0.2,
+//     ),
+//     look: (
+//         invert_y: true,
+//     ),
+// )
+#[derive(Serialize, Deserialize, Default)]
+struct BindingsFile {
+    #[serde(default)]
+    layout: KeyboardLayout,
+    #[serde(default)]
+    keys: HashMap<Action, Vec<KeyCode>>,
+    #[serde(default)]
+    buttons: HashMap<Action, Vec<GamepadButtonType>>,
+    #[serde(default)]
+    gamepad: GamepadConfig,
+    #[serde(default)]
+    look: LookConfig,
+}
+
+impl BindingsFile {
+    fn load() -> Self {
+        let Some(path) = Bindings::path() else {
+            return BindingsFile::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return BindingsFile::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|err| {
+            error!("Failed to read bindings {}: {}", path.display(), err);
+            BindingsFile::default()
+        })
+    }
+}
+
+#[derive(Resource)]
+pub struct Bindings {
+    layout: KeyboardLayout,
+    key_overrides: HashMap<Action, Vec<KeyCode>>,
+    button_overrides: HashMap<Action, Vec<GamepadButtonType>>,
+    pub gamepad: GamepadConfig,
+    pub look: LookConfig,
+    keys: HashMap<Action, Vec<KeyCode>>,
+    buttons: HashMap<Action, Vec<GamepadButtonType>>,
+}
+
+impl Bindings {
+    fn new(file: BindingsFile) -> Self {
+        let buttons = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let buttons = file
+                    .buttons
+                    .get(&action)
+                    .cloned()
+                    .unwrap_or_else(|| default_buttons(action));
+                (action, buttons)
+            })
+            .collect();
+        let mut bindings = Bindings {
+            layout: file.layout,
+            key_overrides: file.keys,
+            button_overrides: file.buttons,
+            gamepad: file.gamepad,
+            look: file.look,
+            keys: HashMap::new(),
+            buttons,
+        };
+        bindings.set_layout(file.layout);
+        bindings
+    }
+
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("maze").join("bindings.ron"))
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = Bindings::path().ok_or("no config directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let file = BindingsFile {
+            layout: self.layout,
+            keys: self.key_overrides.clone(),
+            buttons: self.button_overrides.clone(),
+            gamepad: self.gamepad,
+            look: self.look,
+        };
+        let contents = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())?;
+        fs::write(&path, contents).map_err(|err| err.to_string())
+    }
+
+    pub fn layout(&self) -> KeyboardLayout {
+        self.layout
+    }
+
+    pub fn set_layout(&mut self, layout: KeyboardLayout) {
+        self.layout = layout;
+        self.keys = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let keys = self
+                    .key_overrides
+                    .get(&action)
+                    .cloned()
+                    .unwrap_or_else(|| layout.keys(action));
+                (action, keys)
+            })
+            .collect();
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        &self.keys[&action]
+    }
+
+    pub fn buttons(&self, action: Action) -> &[GamepadButtonType] {
+        &self.buttons[&action]
+    }
+
+    // Bound keys and buttons for prompts like "N or Select", None if nothing is bound
+    pub fn describe(&self, action: Action) -> Option<String> {
+        let names: Vec<String> = self
+            .keys(action)
+            .iter()
+            .map(|key| format!("{:?}", key))
+            .chain(
+                self.buttons(action)
+                    .iter()
+                    .map(|button| format!("{:?}", button)),
+            )
+            .collect();
+        (!names.is_empty()).then(|| names.join(" or "))
+    }
+}
+
+// Read access to the bound actions, use instead of Input<KeyCode> for anything rebindable.
+// Buttons and sticks of every connected gamepad count.
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    keys: Res<'w, Input<KeyCode>>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    gamepads: Res<'w, Gamepads>,
+    bindings: Res<'w, Bindings>,
+}
+
+impl Actions<'_> {
+    fn gamepad_buttons(&self, action: Action) -> impl Iterator<Item = GamepadButton> + '_ {
+        self.gamepads.iter().flat_map(move |gamepad| {
+            self.bindings
+                .buttons(action)
+                .iter()
+                .map(move |button_type| GamepadButton::new(gamepad, *button_type))
+        })
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.keys
+            .any_pressed(self.bindings.keys(action).iter().copied())
+            || self.buttons.any_pressed(self.gamepad_buttons(action))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.keys
+            .any_just_pressed(self.bindings.keys(action).iter().copied())
+            || self.buttons.any_just_pressed(self.gamepad_buttons(action))
+    }
+
+    // Left stick, x to the right and y forward
+    pub fn move_stick(&self) -> Vec2 {
+        self.stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY)
+    }
+
+    // Right stick, x to the right and y up
+    pub fn look_stick(&self) -> Vec2 {
+        self.stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY)
+    }
+
+    pub fn gamepad(&self) -> &GamepadConfig {
+        &self.bindings.gamepad
+    }
+
+    pub fn look(&self) -> &LookConfig {
+        &self.bindings.look
+    }
+
+    fn stick(&self, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+        let axis = |gamepad, axis_type| {
+            self.axes
+                .get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        let stick = self
+            .gamepads
+            .iter()
+            .map(|gamepad| Vec2::new(axis(gamepad, x), axis(gamepad, y)))
+            .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .unwrap_or(Vec2::ZERO);
+
+        // Radial dead zone, rescaled so the output starts from zero at its edge
+        let dead_zone = self.bindings.gamepad.dead_zone.clamp(0.0, 0.99);
+        let length = stick.length();
+        if length <= dead_zone {
+            return Vec2::ZERO;
+        }
+        stick / length * ((length - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+}
+
+fn save_bindings(bindings: Res<Bindings>) {
+    if bindings.is_changed() && !bindings.is_added() {
+        if let Err(err) = bindings.save() {
+            error!("Failed to save bindings: {}", err);
+        }
+    }
+}
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bindings::new(BindingsFile::load()))
+            .add_systems(Update, save_bindings);
+    }
+}
@@ -1,123 +1,412 @@
-use crate::ui::MazeTimer;
-use bevy::input::mouse::MouseMotion;
+use crate::actions::{Action, Actions};
+use crate::maze::{MazeBounds, MazeSpawned};
+use crate::player::{Player, PlayerMovement};
+use crate::state::AppState;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 use bevy_rapier3d::prelude::*;
-use std::time::Instant;
+
+// Camera height above the player's center
+const EYE_HEIGHT: f32 = 0.25;
+// Radius of the sphere cast from the player to the third person camera
+const CAMERA_RADIUS: f32 = 0.1;
 
 #[derive(PartialEq)]
 enum CameraType {
     Fly,
     Player,
+    // Orbits behind the player at CameraSettings::follow_distance
+    ThirdPerson,
+    // Orthographic view of the whole maze from above
+    TopDown,
+}
+
+// Blend from the pose the camera had when switching modes to the pose of the new mode
+struct CameraTransition {
+    from: Transform,
+    // Pose the camera systems put the camera in this frame
+    target: Transform,
+    elapsed: f32,
+}
+
+impl CameraTransition {
+    fn pose(&self, duration: f32) -> Transform {
+        let t = (self.elapsed / duration).clamp(0.0, 1.0);
+        // Smoothstep, eases in and out
+        let t = t * t * (3.0 - 2.0 * t);
+        Transform {
+            translation: self.from.translation.lerp(self.target.translation, t),
+            rotation: self.from.rotation.slerp(self.target.rotation, t),
+            scale: self.target.scale,
+        }
+    }
 }
 
 #[derive(Component)]
 pub struct CameraSettings {
     camera_type: CameraType,
-    speed: f32,
-    sensitivity: f32,
+    pub speed: f32,
+    // Current look angles in radians, tracked here instead of read back from the rotation
+    yaw: f32,
+    pitch: f32,
+    // Angles the smoothed look is heading towards
+    target_yaw: f32,
+    target_pitch: f32,
+    pub follow_distance: f32,
+    // 1.0 fits the whole maze on screen, smaller zooms in
+    top_down_zoom: f32,
+    // Offset of the top down view from the maze center
+    top_down_pan: Vec2,
+    // Seconds to blend between camera modes
+    pub transition_duration: f32,
+    transition: Option<CameraTransition>,
+    // Where the fly camera was left, restored when switching back to it
+    fly_pose: Option<Transform>,
+}
+
+impl CameraSettings {
+    // Whether movement input goes to the player rather than a free camera
+    pub fn controls_player(&self) -> bool {
+        self.camera_type == CameraType::Player || self.camera_type == CameraType::ThirdPerson
+    }
+
+    // Points the look controller along a rotation that was set some other way
+    fn set_look(&mut self, rotation: Quat) {
+        let (yaw, pitch, _) = rotation.to_euler(EulerRot::YXZ);
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.target_yaw = yaw;
+        self.target_pitch = pitch;
+    }
+
+    fn look_rotation(&self) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, self.yaw) * Quat::from_axis_angle(Vec3::X, self.pitch)
+    }
 }
 
 fn setup_camera(mut commands: Commands) {
+    let transform =
+        Transform::from_xyz(-2.0, 2.0, -2.0).looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y);
+    let mut settings = CameraSettings {
+        camera_type: CameraType::Fly,
+        speed: 2.0,
+        yaw: 0.0,
+        pitch: 0.0,
+        target_yaw: 0.0,
+        target_pitch: 0.0,
+        follow_distance: 2.5,
+        top_down_zoom: 1.0,
+        top_down_pan: Vec2::ZERO,
+        transition_duration: 0.4,
+        transition: None,
+        fly_pose: None,
+    };
+    settings.set_look(transform.rotation);
     commands.spawn((
         Camera3dBundle {
-            transform: Transform::from_xyz(-2.0, 2.0, -2.0)
-                .looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y),
+            transform,
             ..default()
         },
-        CameraSettings {
-            camera_type: CameraType::Fly,
-            speed: 2.0,
-            sensitivity: 0.0001,
-        },
+        settings,
     ));
 }
 
-fn switch_camera_type(mut settings: Query<&mut CameraSettings>, keys: Res<Input<KeyCode>>) {
-    if keys.just_pressed(KeyCode::P) {
-        let mut settings = settings.get_single_mut().unwrap();
+fn switch_camera_type(
+    mut camera: Query<(&mut Transform, &mut CameraSettings, &mut Projection)>,
+    player: Query<&Player>,
+    actions: Actions,
+) {
+    if actions.just_pressed(Action::ToggleCamera) {
+        let (mut transform, mut settings, mut projection) = camera.get_single_mut().unwrap();
+        let settings = &mut *settings;
+
+        // Start from what is on screen, which is mid blend when switching again quickly
+        let from = match &settings.transition {
+            Some(transition) => transition.pose(settings.transition_duration),
+            None => *transform,
+        };
+        settings.transition = Some(CameraTransition {
+            from,
+            target: *transform,
+            elapsed: 0.0,
+        });
+        if settings.camera_type == CameraType::Fly {
+            settings.fly_pose = Some(*transform);
+        }
+        let controlled_player = settings.controls_player();
+
+        settings.camera_type = match settings.camera_type {
+            CameraType::Fly => CameraType::Player,
+            CameraType::Player => CameraType::ThirdPerson,
+            CameraType::ThirdPerson => CameraType::TopDown,
+            CameraType::TopDown => CameraType::Fly,
+        };
         if settings.camera_type == CameraType::Fly {
-            settings.camera_type = CameraType::Player;
-            return;
+            if let Some(fly_pose) = settings.fly_pose {
+                *transform = fly_pose;
+                settings.set_look(fly_pose.rotation);
+            }
         }
-        settings.camera_type = CameraType::Fly;
+        // Take over the player's heading rather than turning the player to the camera's
+        if settings.controls_player() && !controlled_player {
+            if let Ok(player) = player.get_single() {
+                settings.yaw = player.yaw;
+                settings.target_yaw = player.yaw;
+            }
+        }
+        // The scaling is filled in from the maze bounds by top_down_view
+        *projection = if settings.camera_type == CameraType::TopDown {
+            OrthographicProjection::default().into()
+        } else {
+            PerspectiveProjection::default().into()
+        };
     }
 }
 
-fn camera_move(
+pub fn camera_move(
     mut cam_query: Query<(&mut Transform, &CameraSettings)>,
-    mut player_query: Query<
-        (&Transform, &mut KinematicCharacterController),
-        Without<CameraSettings>,
-    >,
-    mut maze_timer: ResMut<MazeTimer>,
+    mut player_query: Query<(&mut KinematicCharacterController, &PlayerMovement, &Player)>,
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
+    actions: Actions,
 ) {
     let (mut cam_t, settings) = cam_query.single_mut();
+    if settings.camera_type == CameraType::TopDown {
+        return;
+    }
+    let mut player = player_query.get_single_mut().ok();
+    // The player walks where it faces, the fly camera where the camera looks
+    let rotation = match &player {
+        Some((_, _, player)) if settings.controls_player() => Quat::from_rotation_y(player.yaw),
+        _ => cam_t.rotation,
+    };
     let mut velocity = Vec3::ZERO;
-    let mut speed_multiplier = 1.0;
-    let forward = Vec3::new(cam_t.forward().x, 0.0, cam_t.forward().z).normalize_or_zero();
+    let forward = rotation * Vec3::NEG_Z;
+    let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+    let right = rotation * Vec3::X;
+    for (action, direction) in [
+        (Action::MoveForward, forward),
+        (Action::MoveBackward, -forward),
+        (Action::StrafeRight, right),
+        (Action::StrafeLeft, -right),
+    ] {
+        if actions.pressed(action) {
+            velocity += direction;
+        }
+    }
+    let stick = actions.move_stick();
+    velocity += forward * stick.y + right * stick.x;
+
     if settings.camera_type == CameraType::Fly {
-        for key in keys.get_pressed() {
-            match key {
-                KeyCode::Comma => velocity += forward,
-                KeyCode::O => velocity -= forward,
-                KeyCode::E => velocity += cam_t.right(),
-                KeyCode::A => velocity += cam_t.left(),
-                KeyCode::Space => velocity += Vec3::Y,
-                KeyCode::ShiftLeft => velocity -= Vec3::Y,
-                KeyCode::ControlLeft => speed_multiplier = 10.0,
-                _ => (),
-            }
+        if actions.pressed(Action::Jump) {
+            velocity += Vec3::Y;
         }
-        cam_t.translation +=
-            velocity.normalize_or_zero() * time.delta_seconds() * settings.speed * speed_multiplier;
-    } else {
-        for key in keys.get_pressed() {
-            match key {
-                KeyCode::Comma => velocity += forward,
-                KeyCode::O => velocity -= forward,
-                KeyCode::E => velocity += cam_t.right(),
-                KeyCode::A => velocity += cam_t.left(),
-                KeyCode::Space => velocity += Vec3::Y,
-                KeyCode::ShiftLeft => velocity -= Vec3::Y,
-                KeyCode::ControlLeft => speed_multiplier = 10.0,
-                _ => (),
-            }
+        if actions.pressed(Action::Crouch) {
+            velocity -= Vec3::Y;
         }
-        if !maze_timer.player_started {
-            maze_timer.player_started = true;
-            maze_timer.start_time = Some(Instant::now())
+        let speed_multiplier = if actions.pressed(Action::Sprint) {
+            10.0
+        } else {
+            1.0
+        };
+        // Clamped rather than normalized so a half tilted stick moves at half speed
+        let velocity = velocity.clamp_length_max(1.0);
+        cam_t.translation += velocity * time.delta_seconds() * settings.speed * speed_multiplier;
+    } else if let Some((controller, movement, _)) = &mut player {
+        // Only the horizontal part, player::apply_gravity adds falling and jumping
+        let velocity = velocity.clamp_length_max(1.0);
+        controller.translation = Some(velocity * time.delta_seconds() * movement.speed());
+    }
+}
+
+// Places the camera in the player's head, or behind it pulled in front of any wall in the way
+fn follow_player(
+    mut camera: Query<(&mut Transform, &CameraSettings)>,
+    player: Query<&Transform, (With<Player>, Without<CameraSettings>)>,
+    rapier_context: Res<RapierContext>,
+) {
+    let (mut cam_t, settings) = camera.single_mut();
+    let Ok(player_t) = player.get_single() else {
+        return;
+    };
+    let eye = player_t.translation + Vec3::Y * EYE_HEIGHT;
+    match settings.camera_type {
+        CameraType::Fly | CameraType::TopDown => (),
+        CameraType::Player => cam_t.translation = eye,
+        CameraType::ThirdPerson => {
+            let back = cam_t.back();
+            // Only walls and the floor block the view, not the player or thrown projectiles
+            let distance = rapier_context
+                .cast_shape(
+                    eye,
+                    Quat::IDENTITY,
+                    back,
+                    &Collider::ball(CAMERA_RADIUS),
+                    settings.follow_distance,
+                    QueryFilter::only_fixed().exclude_sensors(),
+                )
+                .map_or(settings.follow_distance, |(_, hit)| hit.toi);
+            cam_t.translation = eye + back * distance;
         }
-        let (player_t, mut controller) = player_query.single_mut();
-        controller.translation = Some(
-            velocity.normalize_or_zero() * time.delta_seconds() * settings.speed * speed_multiplier,
-        );
-        cam_t.translation = player_t.translation + Vec3::new(0.0, 0.25, 0.0);
     }
 }
 
 fn camera_look(
-    mut query: Query<(&mut Transform, &CameraSettings)>,
+    mut query: Query<(&mut Transform, &mut CameraSettings)>,
+    mut player: Query<&mut Player>,
     window: Query<&Window, With<PrimaryWindow>>,
     mut mouse_motion: EventReader<MouseMotion>,
+    actions: Actions,
+    time: Res<Time>,
 ) {
     let window = window.get_single().expect("Failed to find a window");
-    if window.cursor.grab_mode != CursorGrabMode::Locked {
+    let (mut transform, mut settings) = query.single_mut();
+    if settings.camera_type == CameraType::TopDown {
+        mouse_motion.clear();
         return;
     }
 
-    let (mut transform, settings) = query.single_mut();
-    for event in mouse_motion.iter() {
-        let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
-        let window_scale = window.height().min(window.width());
-        pitch -= (settings.sensitivity * event.delta.y * window_scale).to_radians();
-        yaw -= (settings.sensitivity * event.delta.x * window_scale).to_radians();
-        pitch = pitch.clamp(-1.54, 1.54);
-        transform.rotation =
-            Quat::from_axis_angle(Vec3::Y, yaw) * Quat::from_axis_angle(Vec3::X, pitch);
+    // Yaw and pitch deltas in degrees
+    let mut mouse = Vec2::ZERO;
+    if window.cursor.grab_mode == CursorGrabMode::Locked {
+        mouse = mouse_motion.iter().map(|event| event.delta).sum();
+    } else {
+        mouse_motion.clear();
+    }
+    let look = *actions.look();
+    let mut mouse = -mouse * look.sensitivity;
+    if look.invert_y {
+        mouse.y = -mouse.y;
+    }
+    let stick = actions.look_stick() * actions.gamepad().look_sensitivity * time.delta_seconds();
+    let stick = Vec2::new(-stick.x, stick.y);
+
+    let min_pitch = look.min_pitch.to_radians();
+    let max_pitch = look.max_pitch.to_radians().max(min_pitch);
+    // The stick already turns at a steady rate, only the mouse is smoothed
+    settings.yaw += stick.x.to_radians();
+    settings.pitch = (settings.pitch + stick.y.to_radians()).clamp(min_pitch, max_pitch);
+    settings.target_yaw += (stick.x + mouse.x).to_radians();
+    settings.target_pitch =
+        (settings.target_pitch + (stick.y + mouse.y).to_radians()).clamp(min_pitch, max_pitch);
+
+    // Exponential approach, the same over a second no matter how it is split into frames
+    let catch_up = if look.smoothing > 0.0 {
+        1.0 - (-time.delta_seconds() / look.smoothing).exp()
+    } else {
+        1.0
+    };
+    settings.yaw += (settings.target_yaw - settings.yaw) * catch_up;
+    settings.pitch += (settings.target_pitch - settings.pitch) * catch_up;
+    transform.rotation = settings.look_rotation();
+
+    if settings.controls_player() {
+        for mut player in &mut player {
+            // Only touch it when it moves, player::rotate_player reacts to changes
+            if player.yaw != settings.yaw {
+                player.yaw = settings.yaw;
+            }
+        }
+    }
+}
+
+// Frames the maze from above, movement pans and jump, crouch or the mouse wheel zoom
+fn top_down_view(
+    mut camera: Query<(&mut Transform, &mut Projection, &mut CameraSettings)>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    bounds: Res<MazeBounds>,
+    actions: Actions,
+    time: Res<Time>,
+) {
+    let (mut transform, mut projection, mut settings) = camera.single_mut();
+    if settings.camera_type != CameraType::TopDown {
+        mouse_wheel.clear();
+        return;
+    }
+    let Projection::Orthographic(projection) = projection.as_mut() else {
+        return;
+    };
+
+    let mut zoom = 0.0;
+    for event in mouse_wheel.iter() {
+        zoom -= match event.unit {
+            MouseScrollUnit::Line => event.y * 0.1,
+            MouseScrollUnit::Pixel => event.y * 0.002,
+        };
+    }
+    if actions.pressed(Action::Jump) {
+        zoom += time.delta_seconds();
+    }
+    if actions.pressed(Action::Crouch) {
+        zoom -= time.delta_seconds();
+    }
+    settings.top_down_zoom = (settings.top_down_zoom * (1.0 + zoom)).clamp(0.1, 2.0);
+
+    let mut pan = actions.move_stick();
+    for (action, direction) in [
+        (Action::MoveForward, Vec2::Y),
+        (Action::MoveBackward, Vec2::NEG_Y),
+        (Action::StrafeRight, Vec2::X),
+        (Action::StrafeLeft, Vec2::NEG_X),
+    ] {
+        if actions.pressed(action) {
+            pan += direction;
+        }
+    }
+    // Screen right is world -x when looking down with north up
+    let size = bounds.0.size();
+    let pan_speed = size.max_element() * settings.top_down_zoom;
+    settings.top_down_pan +=
+        Vec2::new(-pan.x, pan.y).clamp_length_max(1.0) * pan_speed * time.delta_seconds();
+    settings.top_down_pan = settings.top_down_pan.clamp(-size / 2.0, size / 2.0);
+
+    let center = bounds.0.center() + settings.top_down_pan;
+    *transform = Transform::from_xyz(center.x, 50.0, center.y)
+        .looking_at(Vec3::new(center.x, 0.0, center.y), Vec3::Z);
+    projection.scaling_mode = ScalingMode::AutoMin {
+        min_width: size.x,
+        min_height: size.y,
+    };
+    projection.scale = settings.top_down_zoom;
+}
+
+// A new maze starts looking into it, like the player
+fn face_new_maze(mut spawned: EventReader<MazeSpawned>, mut camera: Query<&mut CameraSettings>) {
+    if let Some(spawned) = spawned.iter().last() {
+        let mut settings = camera.single_mut();
+        if settings.controls_player() {
+            settings.yaw = spawned.yaw;
+            settings.target_yaw = spawned.yaw;
+        }
+    }
+}
+
+// Puts the camera back in the pose the camera systems left it in, undoing last frame's blend
+fn restore_camera_target(mut camera: Query<(&mut Transform, &CameraSettings)>) {
+    let (mut transform, settings) = camera.single_mut();
+    if let Some(transition) = &settings.transition {
+        *transform = transition.target;
+    }
+}
+
+// Runs after every system that places the camera and shows the blended pose instead
+fn animate_camera_transition(
+    mut camera: Query<(&mut Transform, &mut CameraSettings)>,
+    time: Res<Time>,
+) {
+    let (mut transform, mut settings) = camera.single_mut();
+    let settings = &mut *settings;
+    let Some(transition) = &mut settings.transition else {
+        return;
+    };
+    transition.elapsed += time.delta_seconds();
+    transition.target = *transform;
+    if transition.elapsed >= settings.transition_duration {
+        settings.transition = None;
+        return;
     }
+    *transform = transition.pose(settings.transition_duration);
 }
 
 pub struct FlyCameraPlugin;
@@ -125,6 +414,20 @@ pub struct FlyCameraPlugin;
 impl Plugin for FlyCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup_camera)
-            .add_systems(Update, (camera_move, camera_look, switch_camera_type));
+            .add_systems(Update, face_new_maze)
+            .add_systems(
+                Update,
+                (
+                    restore_camera_target,
+                    camera_move,
+                    camera_look,
+                    switch_camera_type,
+                    follow_player,
+                    top_down_view,
+                    animate_camera_transition,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            );
     }
 }
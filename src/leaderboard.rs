@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::maze::{MazeAlgorithm, MazeConfig};
+
+// Times kept for every maze
+pub const TOP_N: usize = 10;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeaderboardKey {
+    pub width: usize,
+    pub height: usize,
+    pub algorithm: MazeAlgorithm,
+    pub seed: u64,
+    // MazeConfig::braid in thousandths, an f64 can't be hashed. Missing from older files.
+    #[serde(default)]
+    pub braid_per_mille: u16,
+    #[serde(default)]
+    pub longest_path: bool,
+}
+
+impl LeaderboardKey {
+    // None for layout files and levels, which are not described by a size, algorithm and seed
+    pub fn from_config(config: &MazeConfig) -> Option<Self> {
+        if config.layout_file.is_some() || config.level.is_some() {
+            return None;
+        }
+        Some(LeaderboardKey {
+            width: config.width,
+            height: config.height,
+            algorithm: config.algorithm,
+            seed: config.seed,
+            braid_per_mille: (config.braid.clamp(0.0, 1.0) * 1000.0).round() as u16,
+            longest_path: config.longest_path,
+        })
+    }
+}
+
+// Best completion times, saved to <data dir>/maze/leaderboard.ron
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct Leaderboard {
+    // Sorted fastest first, at most TOP_N per maze
+    times: HashMap<LeaderboardKey, Vec<Duration>>,
+}
+
+impl Leaderboard {
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("maze").join("leaderboard.ron"))
+    }
+
+    fn load() -> Self {
+        let Some(path) = Leaderboard::path() else {
+            return Leaderboard::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Leaderboard::default();
+        };
+        ron::from_str(&contents).unwrap_or_else(|err| {
+            error!("Failed to read leaderboard {}: {}", path.display(), err);
+            Leaderboard::default()
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = Leaderboard::path().ok_or("no data directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())?;
+        fs::write(&path, contents).map_err(|err| err.to_string())
+    }
+
+    pub fn times(&self, key: &LeaderboardKey) -> &[Duration] {
+        self.times.get(key).map_or(&[], |times| times.as_slice())
+    }
+
+    pub fn best(&self, key: &LeaderboardKey) -> Option<Duration> {
+        self.times(key).first().copied()
+    }
+
+    // Fastest time of every seed played with these settings, the seed of `key` is ignored.
+    // Fastest first.
+    pub fn best_seeds(&self, key: &LeaderboardKey) -> Vec<(u64, Duration)> {
+        let mut seeds: Vec<(u64, Duration)> = self
+            .times
+            .iter()
+            .filter(|(other, _)| {
+                LeaderboardKey {
+                    seed: key.seed,
+                    ..**other
+                } == *key
+            })
+            .filter_map(|(key, times)| times.first().map(|time| (key.seed, *time)))
+            .collect();
+        seeds.sort_by_key(|(_, time)| *time);
+        seeds.truncate(TOP_N);
+        seeds
+    }
+
+    // Returns the rank of the new time, None if it is too slow for the top N
+    pub fn record(&mut self, key: LeaderboardKey, time: Duration) -> Option<usize> {
+        let times = self.times.entry(key).or_default();
+        let rank = times.partition_point(|best| *best <= time);
+        if rank >= TOP_N {
+            return None;
+        }
+        times.insert(rank, time);
+        times.truncate(TOP_N);
+        Some(rank)
+    }
+}
+
+// Numbered list of times, the highlighted rank is marked with an arrow
+pub fn format_times(times: &[Duration], highlight: Option<usize>) -> String {
+    times
+        .iter()
+        .enumerate()
+        .map(|(rank, time)| {
+            let marker = if highlight == Some(rank) { " <" } else { "" };
+            format!("{:>2}. {:>8.2}{}\n", rank + 1, time.as_secs_f32(), marker)
+        })
+        .collect()
+}
+
+fn save_leaderboard(leaderboard: Res<Leaderboard>) {
+    if leaderboard.is_changed() && !leaderboard.is_added() {
+        if let Err(err) = leaderboard.save() {
+            error!("Failed to save leaderboard: {}", err);
+        }
+    }
+}
+
+pub struct LeaderboardPlugin;
+
+impl Plugin for LeaderboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Leaderboard::load())
+            .add_systems(Update, save_leaderboard);
+    }
+}
@@ -1,11 +1,21 @@
+use bevy::asset::ChangeWatcher;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
+use std::time::Duration;
+mod actions;
+use actions::ActionsPlugin;
 mod player;
 use player::PlayerPlugin;
 mod camera;
 use camera::FlyCameraPlugin;
+mod leaderboard;
+use leaderboard::LeaderboardPlugin;
 mod maze;
 use maze::MazePlugin;
+mod menu;
+use menu::MenuPlugin;
+mod state;
+use state::StatePlugin;
 mod scene;
 use scene::ScenePlugin;
 mod ui;
@@ -16,14 +26,22 @@ use utils::UtilsPlugin;
 fn main() {
     App::new()
         .add_plugins((
-            DefaultPlugins,
+            DefaultPlugins.set(AssetPlugin {
+                // Hot reload levels and models while the game is running
+                watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+                ..default()
+            }),
+            ActionsPlugin,
             PlayerPlugin,
             FlyCameraPlugin,
             MazePlugin,
             RapierPhysicsPlugin::<NoUserData>::default(),
             RapierDebugRenderPlugin::default(),
             UIPlugin,
+            MenuPlugin,
+            LeaderboardPlugin,
             ScenePlugin,
+            StatePlugin,
             UtilsPlugin,
         ))
         .run();
@@ -1,6 +1,173 @@
+use crate::actions::{Action, Actions};
+use crate::player::Player;
+use crate::state::AppState;
+use crate::ui::MazeTimer;
+use bevy::asset::LoadState;
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::render_resource::PrimitiveTopology;
 use bevy_rapier3d::prelude::*;
-use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+mod ascii;
+mod asset;
+pub use asset::{MazeAsset, MazeAssetLoader};
+mod distances;
+pub use distances::DistanceMap;
+mod generators;
+pub use generators::{braid, MazeAlgorithm, MazeGenerator};
+mod solver;
+use solver::BreadthFirst;
+pub use solver::{MazeSolver, SolverStrategy};
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct MazeConfig {
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    pub algorithm: MazeAlgorithm,
+    pub solver: SolverStrategy,
+    // Fraction of dead ends opened up after generation, 0.0 keeps the maze perfect
+    pub braid: f64,
+    // One mesh and compound collider for all walls instead of an entity per wall
+    pub merge_walls: bool,
+    // ASCII layout loaded instead of generating a maze
+    pub layout_file: Option<String>,
+    // .maze.ron level inside assets/, replaces the generated maze once loaded
+    pub level: Option<String>,
+    // Place start and finish at the two ends of the longest path instead of opposite corners
+    pub longest_path: bool,
+    pub room_size: f32,
+    pub wall_width: f32,
+    pub wall_height: f32,
+}
+
+impl Default for MazeConfig {
+    fn default() -> Self {
+        MazeConfig {
+            width: 10,
+            height: 10,
+            seed: rand::random(),
+            algorithm: MazeAlgorithm::default(),
+            solver: SolverStrategy::default(),
+            braid: 0.0,
+            merge_walls: true,
+            layout_file: None,
+            level: None,
+            longest_path: false,
+            room_size: 2.0,
+            wall_width: 0.1,
+            wall_height: 1.5,
+        }
+    }
+}
+
+impl MazeConfig {
+    // Center of a room at floor level
+    pub fn cell_position(&self, (row, col): (usize, usize)) -> Vec3 {
+        Vec3::new(
+            col as f32 * (self.room_size + self.wall_width) + self.room_size / 2.0,
+            0.0,
+            row as f32 * (self.room_size + self.wall_width) + self.room_size / 2.0,
+        )
+    }
+}
+
+const HEAT_MAP_STEPS: usize = 16;
+
+// Meshes and materials shared by everything the maze and the player spawn, so asset counts do
+// not grow with the maze size or the number of thrown projectiles
+#[derive(Resource)]
+pub struct MazeAssets {
+    // Unit cube, scaled to size by the entity transform
+    pub block: Handle<Mesh>,
+    pub cube: Handle<Mesh>,
+    pub ball: Handle<Mesh>,
+    pub wall_material: Handle<StandardMaterial>,
+    pub floor_material: Handle<StandardMaterial>,
+    pub projectile_material: Handle<StandardMaterial>,
+    pub solution_material: Handle<StandardMaterial>,
+    pub heat_map_materials: Vec<Handle<StandardMaterial>>,
+}
+
+impl FromWorld for MazeAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mut meshes = world.resource_mut::<Assets<Mesh>>();
+        let block = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
+        let cube = meshes.add(Mesh::from(shape::Cube { size: 0.05 }));
+        let ball = meshes.add(
+            shape::Icosphere {
+                radius: 0.25,
+                ..default()
+            }
+            .try_into()
+            .unwrap(),
+        );
+
+        let overlay = |color: Color| StandardMaterial {
+            base_color: color,
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            ..default()
+        };
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        MazeAssets {
+            block,
+            cube,
+            ball,
+            wall_material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+            floor_material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+            projectile_material: materials.add(Color::rgb(0.2, 0.5, 0.5).into()),
+            solution_material: materials.add(overlay(Color::rgba(0.0, 1.0, 0.0, 0.5))),
+            heat_map_materials: (0..HEAT_MAP_STEPS)
+                .map(|step| {
+                    let heat = step as f32 / (HEAT_MAP_STEPS - 1) as f32;
+                    materials.add(overlay(Color::rgba(heat, 0.0, 1.0 - heat, 0.5)))
+                })
+                .collect(),
+        }
+    }
+}
+
+// Floor of the current maze, x and z of the world mapped to x and y
+#[derive(Resource, Default)]
+pub struct MazeBounds(pub Rect);
+
+// Everything spawned for the current maze, despawned when it is rebuilt
+#[derive(Component)]
+pub struct MazeEntity;
+
+#[derive(Event)]
+pub struct MazeSpawned {
+    pub start: (usize, usize),
+    // Heading in radians around the Y axis that looks out of the start room through an opening
+    pub yaw: f32,
+}
+
+#[derive(Component)]
+pub struct FinishSensor;
+
+// Number of split time checkpoints placed along the solution path
+const CHECKPOINTS: usize = 3;
+
+#[derive(Component)]
+pub struct Checkpoint(pub usize);
+
+#[derive(Event)]
+pub struct CheckpointReached {
+    pub index: usize,
+}
+
+#[derive(Event)]
+pub struct MazeCompleted {
+    pub time: Duration,
+    pub seed: u64,
+}
 
 #[derive(Clone)]
 struct Cell {
@@ -17,12 +184,13 @@ impl Cell {
     }
 }
 
-#[derive(Component)]
-struct Grid {
+#[derive(Component, Clone)]
+pub struct Grid {
     width: usize,
     height: usize,
     grid: Vec<Vec<Cell>>,
-    stack: Vec<(usize, usize)>,
+    start: (usize, usize),
+    finish: (usize, usize),
 }
 
 impl Grid {
@@ -31,11 +199,12 @@ impl Grid {
             width,
             height,
             grid: vec![vec![Cell::new(); width]; height],
-            stack: Vec::new(),
+            start: (0, 0),
+            finish: (height - 1, width - 1),
         }
     }
 
-    fn check_neighbors(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+    fn neighbors(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
         let mut result = Vec::new();
         let directions = [(-1, 0), (1, 0), (0, -1), (0, 1)];
 
@@ -47,139 +216,355 @@ impl Grid {
                 let neighbor_col = neighbor_col as usize;
 
                 if neighbor_row < self.height && neighbor_col < self.width {
-                    if !self.grid[neighbor_row][neighbor_col].visited {
-                        result.push((neighbor_row, neighbor_col));
-                    }
+                    result.push((neighbor_row, neighbor_col));
                 }
             }
         }
         result
     }
+
+    fn check_neighbors(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+        self.neighbors(row, col)
+            .into_iter()
+            .filter(|&(row, col)| !self.grid[row][col].visited)
+            .collect()
+    }
+
+    fn visited_neighbors(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+        self.neighbors(row, col)
+            .into_iter()
+            .filter(|&(row, col)| self.grid[row][col].visited)
+            .collect()
+    }
+
+    // Walls are stored on the lower cell of each pair: walls[0] separates a cell from the
+    // one in the next row, walls[1] from the one in the next column.
+    fn wall_mut(&mut self, a: (usize, usize), b: (usize, usize)) -> Option<&mut bool> {
+        match (b.0 as isize - a.0 as isize, b.1 as isize - a.1 as isize) {
+            (1, 0) => Some(&mut self.grid[a.0][a.1].walls[0]),
+            (-1, 0) => Some(&mut self.grid[b.0][b.1].walls[0]),
+            (0, 1) => Some(&mut self.grid[a.0][a.1].walls[1]),
+            (0, -1) => Some(&mut self.grid[b.0][b.1].walls[1]),
+            _ => None,
+        }
+    }
+
+    fn has_wall(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+        match (b.0 as isize - a.0 as isize, b.1 as isize - a.1 as isize) {
+            (1, 0) => self.grid[a.0][a.1].walls[0],
+            (-1, 0) => self.grid[b.0][b.1].walls[0],
+            (0, 1) => self.grid[a.0][a.1].walls[1],
+            (0, -1) => self.grid[b.0][b.1].walls[1],
+            _ => true,
+        }
+    }
+
+    fn open_neighbors(&self, row: usize, col: usize) -> Vec<(usize, usize)> {
+        self.neighbors(row, col)
+            .into_iter()
+            .filter(|&neighbor| !self.has_wall((row, col), neighbor))
+            .collect()
+    }
+
+    fn remove_wall(&mut self, a: (usize, usize), b: (usize, usize)) {
+        if let Some(wall) = self.wall_mut(a, b) {
+            *wall = false;
+        }
+    }
+
+    fn add_wall(&mut self, a: (usize, usize), b: (usize, usize)) {
+        if let Some(wall) = self.wall_mut(a, b) {
+            *wall = true;
+        }
+    }
+
+    // Opens every interior wall, keeping the outer boundary closed
+    fn clear_walls(&mut self) {
+        for (row_i, row) in self.grid.iter_mut().enumerate() {
+            for (col_i, cell) in row.iter_mut().enumerate() {
+                cell.walls[0] = row_i == self.height - 1;
+                cell.walls[1] = col_i == self.width - 1;
+            }
+        }
+    }
+}
+
+fn generate_grid(config: &MazeConfig) -> Grid {
+    // The inspector can set a size of 0, which Grid and the generators can't handle
+    let width = config.width.max(1);
+    let height = config.height.max(1);
+    info!(
+        "Generating {}x{} maze with {:?} and seed {}",
+        width, height, config.algorithm, config.seed
+    );
+    // Unlike StdRng, ChaCha8 output is stable across rand versions and platforms, so a seed
+    // keeps naming the same maze
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+
+    let mut grid = Grid::new(width, height);
+    config.algorithm.generator().generate(&mut grid, &mut rng);
+    if config.braid > 0.0 {
+        braid(&mut grid, config.braid, &mut rng);
+    }
+
+    if config.longest_path {
+        // The farthest cell from anywhere is one end of the longest path, the farthest cell
+        // from that end is the other
+        grid.start = DistanceMap::new(&grid, grid.start).farthest();
+        grid.finish = DistanceMap::new(&grid, grid.start).farthest();
+    }
+    grid
+}
+
+fn load_grid(path: &str) -> Result<Grid, String> {
+    info!("Loading maze layout from {}", path);
+    std::fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| text.parse())
 }
 
-fn create_grid(mut commands: Commands) {
-    let mut rng = rand::thread_rng();
+fn build_grid(config: &MazeConfig) -> Grid {
+    let grid = match &config.layout_file {
+        Some(path) => load_grid(path).unwrap_or_else(|err| {
+            error!("Failed to load maze layout {}: {}", path, err);
+            generate_grid(config)
+        }),
+        None => generate_grid(config),
+    };
 
-    let mut grid = Grid::new(2, 2);
-    let current_cell = (0, 0);
+    if BreadthFirst.solve(&grid, grid.start, grid.finish).is_none() {
+        warn!("Maze has no path from start to finish");
+    }
+    grid
+}
 
-    grid.grid[current_cell.0][current_cell.1].visited = true;
-    grid.stack.push(current_cell);
+// Replaces the current maze at runtime. Without a seed a new random one is picked.
+#[derive(Event)]
+pub struct RegenerateMaze {
+    pub seed: Option<u64>,
+}
 
-    while !grid.stack.is_empty() {
-        let current_cell = grid.stack.pop().unwrap();
+#[derive(SystemParam)]
+struct MazeRebuilder<'w, 's> {
+    commands: Commands<'w, 's>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    maze_assets: Res<'w, MazeAssets>,
+    grid: Query<'w, 's, Entity, With<Grid>>,
+    maze_entities: Query<'w, 's, Entity, With<MazeEntity>>,
+    spawned: EventWriter<'w, MazeSpawned>,
+}
 
-        let neighbors = grid.check_neighbors(current_cell.0, current_cell.1);
-        if !neighbors.is_empty() {
-            grid.stack.push(current_cell);
-            let neighbor = neighbors[rng.gen_range(0..neighbors.len())];
+impl MazeRebuilder<'_, '_> {
+    // Despawns everything owned by the old maze and spawns the new grid in its place
+    fn rebuild(&mut self, config: &MazeConfig, grid: Grid) {
+        for entity in self.maze_entities.iter() {
+            self.commands.entity(entity).despawn_recursive();
+        }
+        spawn_maze(
+            &mut self.commands,
+            &mut self.meshes,
+            &self.maze_assets,
+            config,
+            &grid,
+        );
 
-            match (
-                neighbor.0 as isize - current_cell.0 as isize,
-                neighbor.1 as isize - current_cell.1 as isize,
-            ) {
-                (-1, 0) => grid.grid[neighbor.0][neighbor.1].walls[0] = false, // South
-                (1, 0) => grid.grid[current_cell.0][current_cell.1].walls[0] = false, // North
-                (0, -1) => grid.grid[neighbor.0][neighbor.1].walls[1] = false, // West
-                (0, 1) => grid.grid[current_cell.0][current_cell.1].walls[1] = false, // East
-                _ => (),
+        let distances = DistanceMap::new(&grid, grid.start);
+        let (row, col) = grid.start;
+        // Rows run along +Z and columns along +X, a yaw of 0 looks down -Z
+        let yaw = grid
+            .open_neighbors(row, col)
+            .first()
+            .map_or(PI, |&(next_row, next_col)| {
+                f32::atan2(col as f32 - next_col as f32, row as f32 - next_row as f32)
+            });
+        self.spawned.send(MazeSpawned {
+            start: grid.start,
+            yaw,
+        });
+        match self.grid.get_single() {
+            Ok(grid_entity) => {
+                self.commands.entity(grid_entity).insert((grid, distances));
             }
+            Err(_) => {
+                self.commands.spawn((grid, distances));
+            }
+        }
+    }
 
-            grid.grid[neighbor.0][neighbor.1].visited = true;
-            grid.stack.push(neighbor);
+    fn rebuild_level(
+        &mut self,
+        config: &MazeConfig,
+        level: &MazeAsset,
+        asset_server: &AssetServer,
+    ) {
+        self.rebuild(config, level.grid.clone());
+        for decoration in &level.decorations {
+            let mut transform = Transform::from_translation(config.cell_position(decoration.cell))
+                .with_scale(Vec3::splat(decoration.scale));
+            transform.rotate_y(decoration.rotation.to_radians());
+            self.commands.spawn((
+                SceneBundle {
+                    scene: asset_server.load(format!("{}#Scene0", decoration.model)),
+                    transform,
+                    ..default()
+                },
+                MazeEntity,
+            ));
         }
     }
+}
 
-    commands.spawn(grid);
+fn regenerate_on_key(actions: Actions, mut regenerate: EventWriter<RegenerateMaze>) {
+    if actions.just_pressed(Action::NewMaze) {
+        regenerate.send(RegenerateMaze { seed: None });
+    }
 }
 
-fn display_grid(
-    grid: Query<&Grid>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+fn regenerate_maze(
+    mut events: EventReader<RegenerateMaze>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut config: ResMut<MazeConfig>,
 ) {
-    let grid = grid.single();
-    let room_size = 2.0;
-    let wall_width = 0.1;
-    let wall_height = 1.5;
+    let Some(event) = events.iter().last() else {
+        return;
+    };
+    config.seed = event.seed.unwrap_or_else(rand::random);
+    next_state.set(AppState::Loading);
+}
+
+// Builds the maze while in AppState::Loading, waiting for the level asset if one is configured
+fn build_maze(
+    mut next_state: ResMut<NextState<AppState>>,
+    mut rebuilder: MazeRebuilder,
+    asset_server: Res<AssetServer>,
+    config: Res<MazeConfig>,
+    level: Option<Res<MazeLevel>>,
+    levels: Res<Assets<MazeAsset>>,
+) {
+    if let Some(level) = &level {
+        if let Some(asset) = levels.get(&level.handle) {
+            rebuilder.rebuild_level(&config, asset, &asset_server);
+            next_state.set(AppState::Playing);
+            return;
+        }
+        if asset_server.get_load_state(&level.handle) != LoadState::Failed {
+            return;
+        }
+        error!("Failed to load level, generating a maze instead");
+    }
+
+    rebuilder.rebuild(&config, build_grid(&config));
+    next_state.set(AppState::Playing);
+}
+
+fn export_grid(actions: Actions, config: Res<MazeConfig>, grid: Query<&Grid>) {
+    if actions.just_pressed(Action::ExportMaze) {
+        let grid = grid.single();
+        let path = format!("maze_{}.txt", config.seed);
+        match std::fs::write(&path, grid.to_string()) {
+            Ok(()) => info!("Saved maze to {}\n{}", path, grid),
+            Err(err) => error!("Failed to save maze to {}: {}", path, err),
+        }
+    }
+}
+
+// Room sized trigger volume tagged with `marker`
+fn spawn_sensor(
+    commands: &mut Commands,
+    config: &MazeConfig,
+    cell: (usize, usize),
+    marker: impl Component,
+) {
+    let room_size = config.room_size;
+    let wall_height = config.wall_height;
+    commands.spawn((
+        Sensor,
+        TransformBundle::from(Transform::from_translation(
+            config.cell_position(cell) + Vec3::Y * wall_height / 2.0,
+        )),
+        Collider::cuboid(room_size / 2.0, wall_height / 2.0, room_size / 2.0),
+        Name::new("Sensor"),
+        MazeEntity,
+        marker,
+        ActiveEvents::COLLISION_EVENTS,
+        // The player is kinematic, which is not checked against fixed colliders by default
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+    ));
+}
+
+fn spawn_maze(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    maze_assets: &MazeAssets,
+    config: &MazeConfig,
+    grid: &Grid,
+) {
+    let room_size = config.room_size;
+    let wall_width = config.wall_width;
+    let wall_height = config.wall_height;
     let wall_depth = room_size + wall_width * 2.0;
 
-    // Floor
     let maze_width = (room_size + wall_width) * grid.width as f32 + wall_width;
     let maze_height = (room_size + wall_width) * grid.height as f32 + wall_width;
+    commands.insert_resource(MazeBounds(Rect::new(
+        -wall_width,
+        -wall_width,
+        maze_width - wall_width,
+        maze_height - wall_width,
+    )));
 
+    // Floor
     commands.spawn((
         PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Box::new(
-                maze_width,
-                wall_width,
-                maze_height,
-            ))),
-            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+            mesh: maze_assets.block.clone(),
+            material: maze_assets.floor_material.clone(),
             transform: Transform::from_xyz(
                 maze_width / 2.0 - wall_width,
                 -wall_width / 2.0,
                 maze_height / 2.0 - wall_width,
-            ),
+            )
+            .with_scale(Vec3::new(maze_width, wall_width, maze_height)),
             ..default()
         },
+        MazeEntity,
         RigidBody::Fixed,
-        Collider::cuboid(maze_width / 2.0, wall_width / 2.0, maze_height / 2.0),
+        Collider::cuboid(0.5, 0.5, 0.5),
     ));
 
-    // South
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Box::new(
-                maze_width,
-                wall_height,
-                wall_width,
-            ))),
-            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-            transform: Transform::from_xyz(
+    // (center, size) of every wall
+    let mut blocks = vec![
+        // South
+        (
+            Vec3::new(
                 maze_width / 2.0 - wall_width,
                 wall_height / 2.0,
                 -wall_width / 2.0,
             ),
-            ..default()
-        },
-        RigidBody::Fixed,
-        Collider::cuboid(maze_width / 2.0, wall_height / 2.0, wall_width / 2.0),
-    ));
-
-    // West
-    commands.spawn((
-        PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Box::new(
-                wall_width,
-                wall_height,
-                maze_height,
-            ))),
-            material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-            transform: Transform::from_xyz(
+            Vec3::new(maze_width, wall_height, wall_width),
+        ),
+        // West
+        (
+            Vec3::new(
                 -wall_width / 2.0,
                 wall_height / 2.0,
                 maze_height / 2.0 - wall_width,
             ),
-            ..default()
-        },
-        RigidBody::Fixed,
-        Collider::cuboid(wall_width / 2.0, wall_height / 2.0, maze_height / 2.0),
-    ));
+            Vec3::new(wall_width, wall_height, maze_height),
+        ),
+    ];
 
     // Finish room sensor collider
-    commands.spawn((
-        Sensor,
-        TransformBundle::from(Transform::from_xyz(
-            (grid.width - 1) as f32 * (room_size + wall_width) + room_size / 2.0,
-            wall_height / 2.0,
-            (grid.height - 1) as f32 * (room_size + wall_width) + room_size / 2.0,
-        )),
-        Collider::cuboid(room_size / 2.0, wall_height / 2.0, room_size / 2.0),
-        Name::new("Sensor"),
-        ActiveEvents::COLLISION_EVENTS,
-    ));
+    spawn_sensor(commands, config, grid.finish, FinishSensor);
+
+    // Checkpoints split the solution path into equal parts
+    if let Some(path) = BreadthFirst.solve(grid, grid.start, grid.finish) {
+        for index in 0..CHECKPOINTS {
+            let cell = path[path.len() * (index + 1) / (CHECKPOINTS + 1)];
+            if cell != grid.start && cell != grid.finish {
+                spawn_sensor(commands, config, cell, Checkpoint(index));
+            }
+        }
+    }
 
     for (row_i, row) in grid.grid.iter().enumerate() {
         for (col_i, cell) in row.iter().enumerate() {
@@ -200,55 +585,321 @@ fn display_grid(
             // });
             // North
             if cell.walls[1] {
-                commands.spawn((
-                    PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Box::new(
-                            wall_width,
-                            wall_height,
-                            wall_depth,
-                        ))),
-                        material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-                        transform: Transform::from_xyz(
-                            col_i as f32 * (room_size + wall_width) + room_size + wall_width / 2.0,
-                            wall_height / 2.0,
-                            row_i as f32 * (room_size + wall_width) + (room_size / 2.0),
-                        ),
-                        ..default()
-                    },
-                    RigidBody::Fixed,
-                    Collider::cuboid(wall_width / 2.0, wall_height / 2.0, wall_depth / 2.0),
+                blocks.push((
+                    Vec3::new(
+                        col_i as f32 * (room_size + wall_width) + room_size + wall_width / 2.0,
+                        wall_height / 2.0,
+                        row_i as f32 * (room_size + wall_width) + (room_size / 2.0),
+                    ),
+                    Vec3::new(wall_width, wall_height, wall_depth),
                 ));
             }
             // East
             if cell.walls[0] {
-                commands.spawn((
-                    PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Box::new(
-                            wall_depth,
-                            wall_height,
-                            wall_width,
-                        ))),
-                        material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-                        transform: Transform::from_xyz(
-                            col_i as f32 * (room_size + wall_width) + (room_size / 2.0),
-                            wall_height / 2.0,
-                            row_i as f32 * (room_size + wall_width) + room_size + wall_width / 2.0,
-                        ),
-                        ..default()
-                    },
-                    RigidBody::Fixed,
-                    Collider::cuboid(wall_depth / 2.0, wall_height / 2.0, wall_width / 2.0),
+                blocks.push((
+                    Vec3::new(
+                        col_i as f32 * (room_size + wall_width) + (room_size / 2.0),
+                        wall_height / 2.0,
+                        row_i as f32 * (room_size + wall_width) + room_size + wall_width / 2.0,
+                    ),
+                    Vec3::new(wall_depth, wall_height, wall_width),
                 ));
             }
         }
     }
+
+    if config.merge_walls {
+        let colliders = blocks
+            .iter()
+            .map(|&(center, size)| {
+                (
+                    center,
+                    Quat::IDENTITY,
+                    Collider::cuboid(size.x / 2.0, size.y / 2.0, size.z / 2.0),
+                )
+            })
+            .collect();
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(merge_blocks(&blocks)),
+                material: maze_assets.wall_material.clone(),
+                ..default()
+            },
+            MazeEntity,
+            RigidBody::Fixed,
+            Collider::compound(colliders),
+        ));
+        return;
+    }
+
+    // Colliders are scaled along with the unit block
+    for (center, size) in blocks {
+        commands.spawn((
+            PbrBundle {
+                mesh: maze_assets.block.clone(),
+                material: maze_assets.wall_material.clone(),
+                transform: Transform::from_translation(center).with_scale(size),
+                ..default()
+            },
+            MazeEntity,
+            RigidBody::Fixed,
+            Collider::cuboid(0.5, 0.5, 0.5),
+        ));
+    }
+}
+
+// Bakes (center, size) boxes into a single mesh so the whole maze is one draw call
+fn merge_blocks(blocks: &[(Vec3, Vec3)]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    for &(center, size) in blocks {
+        let block = Mesh::from(shape::Box::new(size.x, size.y, size.z));
+        let offset = positions.len() as u32;
+
+        if let Some(VertexAttributeValues::Float32x3(values)) =
+            block.attribute(Mesh::ATTRIBUTE_POSITION)
+        {
+            positions.extend(
+                values
+                    .iter()
+                    .map(|&position| (Vec3::from(position) + center).to_array()),
+            );
+        }
+        if let Some(VertexAttributeValues::Float32x3(values)) =
+            block.attribute(Mesh::ATTRIBUTE_NORMAL)
+        {
+            normals.extend_from_slice(values);
+        }
+        if let Some(VertexAttributeValues::Float32x2(values)) =
+            block.attribute(Mesh::ATTRIBUTE_UV_0)
+        {
+            uvs.extend_from_slice(values);
+        }
+        if let Some(Indices::U32(values)) = block.indices() {
+            indices.extend(values.iter().map(|index| index + offset));
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[derive(Resource)]
+struct MazeLevel {
+    path: String,
+    handle: Handle<MazeAsset>,
+}
+
+// Runs on entering AppState::Loading, so picking another level in the menu takes effect
+fn load_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<MazeConfig>,
+    level: Option<Res<MazeLevel>>,
+) {
+    match &config.level {
+        Some(path) if level.is_some_and(|level| level.path == *path) => (),
+        Some(path) => commands.insert_resource(MazeLevel {
+            path: path.clone(),
+            handle: asset_server.load(path.as_str()),
+        }),
+        None => commands.remove_resource::<MazeLevel>(),
+    }
+}
+
+// Rebuilds the maze whenever the level file changes on disk
+fn apply_level(
+    mut events: EventReader<AssetEvent<MazeAsset>>,
+    mut rebuilder: MazeRebuilder,
+    asset_server: Res<AssetServer>,
+    config: Res<MazeConfig>,
+    level: Option<Res<MazeLevel>>,
+    levels: Res<Assets<MazeAsset>>,
+) {
+    let Some(level) = level else {
+        return;
+    };
+    let modified = events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => *handle == level.handle,
+        AssetEvent::Created { .. } | AssetEvent::Removed { .. } => false,
+    });
+    if let Some(asset) = levels.get(&level.handle).filter(|_| modified) {
+        rebuilder.rebuild_level(&config, asset, &asset_server);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn detect_sensors(
+    mut collisions: EventReader<CollisionEvent>,
+    mut completed: EventWriter<MazeCompleted>,
+    mut checkpoint_reached: EventWriter<CheckpointReached>,
+    finish: Query<(), With<FinishSensor>>,
+    checkpoints: Query<&Checkpoint>,
+    player: Query<(), With<Player>>,
+    maze_timer: Res<MazeTimer>,
+    config: Res<MazeConfig>,
+) {
+    for collision in collisions.iter() {
+        let CollisionEvent::Started(a, b, _) = *collision else {
+            continue;
+        };
+        let sensor = if player.contains(a) {
+            b
+        } else if player.contains(b) {
+            a
+        } else {
+            continue;
+        };
+
+        if finish.contains(sensor) && maze_timer.finish_time.is_none() {
+            completed.send(MazeCompleted {
+                time: maze_timer.time,
+                seed: config.seed,
+            });
+        }
+        if let Ok(checkpoint) = checkpoints.get(sensor) {
+            checkpoint_reached.send(CheckpointReached {
+                index: checkpoint.0,
+            });
+        }
+    }
+}
+
+#[derive(Component)]
+struct SolutionOverlay;
+
+#[derive(Component)]
+struct HeatMapOverlay;
+
+fn toggle_solution(
+    mut commands: Commands,
+    maze_assets: Res<MazeAssets>,
+    actions: Actions,
+    config: Res<MazeConfig>,
+    grid: Query<&Grid>,
+    overlay: Query<Entity, With<SolutionOverlay>>,
+) {
+    if !actions.just_pressed(Action::ToggleSolution) {
+        return;
+    }
+    if !overlay.is_empty() {
+        for entity in overlay.iter() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let grid = grid.single();
+    let Some(path) = config.solver.solver().solve(grid, grid.start, grid.finish) else {
+        warn!("{:?} found no solution", config.solver);
+        return;
+    };
+
+    let scale = Vec3::new(config.room_size / 2.0, 0.01, config.room_size / 2.0);
+    for cell in path {
+        commands.spawn((
+            PbrBundle {
+                mesh: maze_assets.block.clone(),
+                material: maze_assets.solution_material.clone(),
+                transform: Transform::from_translation(config.cell_position(cell))
+                    .with_scale(scale),
+                ..default()
+            },
+            SolutionOverlay,
+            MazeEntity,
+        ));
+    }
+}
+
+fn toggle_heat_map(
+    mut commands: Commands,
+    maze_assets: Res<MazeAssets>,
+    actions: Actions,
+    config: Res<MazeConfig>,
+    grid: Query<(&Grid, &DistanceMap)>,
+    overlay: Query<Entity, With<HeatMapOverlay>>,
+) {
+    if !actions.just_pressed(Action::ToggleHeatMap) {
+        return;
+    }
+    if !overlay.is_empty() {
+        for entity in overlay.iter() {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+
+    let (grid, distances) = grid.single();
+    let max = distances.max().max(1) as f32;
+    let scale = Vec3::new(config.room_size, 0.005, config.room_size);
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            let Some(distance) = distances.get((row, col)) else {
+                continue;
+            };
+            let step = (distance as f32 / max * (HEAT_MAP_STEPS - 1) as f32).round() as usize;
+            commands.spawn((
+                PbrBundle {
+                    mesh: maze_assets.block.clone(),
+                    material: maze_assets.heat_map_materials[step].clone(),
+                    transform: Transform::from_translation(config.cell_position((row, col)))
+                        .with_scale(scale),
+                    ..default()
+                },
+                HeatMapOverlay,
+                MazeEntity,
+            ));
+        }
+    }
 }
 
 pub struct MazePlugin;
 
 impl Plugin for MazePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, create_grid)
-            .add_systems(Startup, display_grid);
+        app.init_resource::<MazeConfig>()
+            .init_resource::<MazeAssets>()
+            .init_resource::<MazeBounds>()
+            .register_type::<MazeConfig>()
+            .add_asset::<MazeAsset>()
+            .init_asset_loader::<MazeAssetLoader>()
+            .add_event::<MazeSpawned>()
+            .add_event::<RegenerateMaze>()
+            .add_event::<MazeCompleted>()
+            .add_event::<CheckpointReached>()
+            .add_systems(OnEnter(AppState::Loading), load_level)
+            .add_systems(Update, build_maze.run_if(in_state(AppState::Loading)))
+            .add_systems(
+                Update,
+                (
+                    apply_level,
+                    detect_sensors,
+                    toggle_solution,
+                    toggle_heat_map,
+                    export_grid,
+                )
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (
+                    regenerate_on_key
+                        .run_if(in_state(AppState::Playing).or_else(in_state(AppState::Finished))),
+                    // The pause menu restarts through RegenerateMaze as well
+                    regenerate_maze.run_if(
+                        in_state(AppState::Playing)
+                            .or_else(in_state(AppState::Paused))
+                            .or_else(in_state(AppState::Finished)),
+                    ),
+                )
+                    .chain(),
+            );
     }
 }
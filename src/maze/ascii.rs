@@ -0,0 +1,237 @@
+use super::Grid;
+use std::fmt;
+use std::str::FromStr;
+
+// Text layout with the last row at the top, so north points up:
+//
+// +--+--+--+
+// |F       |
+// +--+  +  +
+// |S    |  |
+// +--+--+--+
+//
+// Every room is two characters wide, S and F mark the start and finish rooms.
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in (0..self.height).rev() {
+            write!(f, "+")?;
+            for cell in &self.grid[row] {
+                write!(f, "{}", if cell.walls[0] { "--+" } else { "  +" })?;
+            }
+            writeln!(f)?;
+
+            write!(f, "|")?;
+            for (col, cell) in self.grid[row].iter().enumerate() {
+                let room = if (row, col) == self.start {
+                    "S "
+                } else if (row, col) == self.finish {
+                    "F "
+                } else {
+                    "  "
+                };
+                write!(f, "{}{}", room, if cell.walls[1] { "|" } else { " " })?;
+            }
+            writeln!(f)?;
+        }
+
+        write!(f, "+")?;
+        for _ in 0..self.width {
+            write!(f, "--+")?;
+        }
+        writeln!(f)
+    }
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<Vec<char>> = s
+            .lines()
+            .map(|line| line.trim_end().chars().collect())
+            .filter(|line: &Vec<char>| !line.is_empty())
+            .collect();
+        if lines.len() < 3 || lines.len().is_multiple_of(2) {
+            return Err(format!(
+                "expected an odd number of lines, got {}",
+                lines.len()
+            ));
+        }
+        let line_width = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+        if line_width < 4 || !(line_width - 1).is_multiple_of(3) {
+            return Err(format!(
+                "line width {} is not a whole number of rooms",
+                line_width
+            ));
+        }
+
+        let width = (line_width - 1) / 3;
+        let height = lines.len() / 2;
+        // Editors like to strip trailing whitespace, missing characters count as open space
+        let at = |line: usize, column: usize| lines[line].get(column).copied().unwrap_or(' ');
+
+        // Grid has no outer walls to open, so a gap in the border would be silently lost
+        for col in 0..width {
+            for line in [0, lines.len() - 1] {
+                if at(line, 3 * col + 1) != '-' || at(line, 3 * col + 2) != '-' {
+                    return Err(format!("gap in the outer wall on line {}", line + 1));
+                }
+            }
+        }
+        for row in 0..height {
+            let room_line = 2 * row + 1;
+            if at(room_line, 0) != '|' || at(room_line, 3 * width) != '|' {
+                return Err(format!("gap in the outer wall on line {}", room_line + 1));
+            }
+        }
+
+        let unexpected = |line: usize, column: usize| {
+            format!(
+                "unexpected '{}' on line {}, column {}",
+                at(line, column),
+                line + 1,
+                column + 1
+            )
+        };
+        for line in (0..lines.len()).step_by(2) {
+            for col in 0..=width {
+                if at(line, 3 * col) != '+' {
+                    return Err(unexpected(line, 3 * col));
+                }
+            }
+        }
+
+        let mut grid = Grid::new(width, height);
+        let mut start = None;
+        let mut finish = None;
+        for row in 0..height {
+            let wall_line = 2 * (height - 1 - row);
+            let room_line = wall_line + 1;
+            for col in 0..width {
+                let cell = &mut grid.grid[row][col];
+                cell.visited = true;
+                cell.walls[0] = match (at(wall_line, 3 * col + 1), at(wall_line, 3 * col + 2)) {
+                    ('-', '-') => true,
+                    (' ', ' ') => false,
+                    ('-' | ' ', _) => return Err(unexpected(wall_line, 3 * col + 2)),
+                    _ => return Err(unexpected(wall_line, 3 * col + 1)),
+                };
+                cell.walls[1] = match at(room_line, 3 * col + 3) {
+                    '|' => true,
+                    ' ' => false,
+                    _ => return Err(unexpected(room_line, 3 * col + 3)),
+                };
+
+                // Markers go in the first of the room's two columns, like Display writes them
+                if at(room_line, 3 * col + 2) != ' ' {
+                    return Err(unexpected(room_line, 3 * col + 2));
+                }
+                let marker = match at(room_line, 3 * col + 1) {
+                    'S' | 's' => &mut start,
+                    'F' | 'f' => &mut finish,
+                    ' ' => continue,
+                    _ => return Err(unexpected(room_line, 3 * col + 1)),
+                };
+                if marker.replace((row, col)).is_some() {
+                    return Err(format!(
+                        "second '{}' on line {}",
+                        at(room_line, 3 * col + 1),
+                        room_line + 1
+                    ));
+                }
+            }
+        }
+
+        if let Some(start) = start {
+            grid.start = start;
+        }
+        if let Some(finish) = finish {
+            grid.finish = finish;
+        }
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::MazeAlgorithm;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use serde::Deserialize;
+
+    fn walls(grid: &Grid) -> Vec<[bool; 2]> {
+        grid.grid.iter().flatten().map(|cell| cell.walls).collect()
+    }
+
+    #[test]
+    fn round_trips_every_generator() {
+        for algorithm in MazeAlgorithm::ALL {
+            let mut grid = Grid::new(9, 6);
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            algorithm.generator().generate(&mut grid, &mut rng);
+            grid.start = (1, 2);
+            grid.finish = (4, 8);
+
+            let text = grid.to_string();
+            let parsed: Grid = text
+                .parse()
+                .unwrap_or_else(|err| panic!("{:?}: {}\n{}", algorithm, err, text));
+            assert_eq!((parsed.width, parsed.height), (9, 6), "{:?}", algorithm);
+            assert_eq!(walls(&parsed), walls(&grid), "{:?}", algorithm);
+            assert_eq!(parsed.start, grid.start, "{:?}", algorithm);
+            assert_eq!(parsed.finish, grid.finish, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn parses_example_level() {
+        #[derive(Deserialize)]
+        struct Level {
+            walls: Vec<String>,
+        }
+        let level: Level =
+            ron::from_str(include_str!("../../assets/levels/example.maze.ron")).unwrap();
+        let grid: Grid = level.walls.join("\n").parse().unwrap();
+        assert_eq!((grid.width, grid.height), (4, 3));
+        assert_eq!(grid.start, (0, 0));
+        assert_eq!(grid.finish, (2, 0));
+    }
+
+    #[test]
+    fn rejects_gaps_in_the_outer_wall() {
+        let closed = ["+--+--+", "|F    |", "+  +--+", "|S    |", "+--+--+"];
+        assert!(closed.join("\n").parse::<Grid>().is_ok());
+
+        for (line, column, gap) in [(0, 4, ' '), (4, 1, ' '), (1, 0, ' '), (3, 6, ' ')] {
+            let mut lines: Vec<String> = closed.iter().map(|line| line.to_string()).collect();
+            lines[line].replace_range(column..column + 1, &gap.to_string());
+            assert!(
+                lines.join("\n").parse::<Grid>().is_err(),
+                "accepted:\n{}",
+                lines.join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_typos() {
+        for text in [
+            // Marker in the second column of a room
+            "+--+--+\n| S F |\n+--+--+",
+            // Corner that is not a '+'
+            "+--+--+\n|S  F |\n+-----+",
+            "+--+--+\n|F    |\n+  -  +\n|S    |\n+--+--+",
+            // Half a wall
+            "+--+--+\n|F    |\n+ -+--+\n|S    |\n+--+--+",
+            // Something else where a wall goes
+            "+--+--+\n|S xF |\n+--+--+",
+            // Two starts or finishes
+            "+--+--+\n|S  S |\n+--+--+",
+            "+--+--+\n|F  F |\n+--+--+",
+        ] {
+            assert!(text.parse::<Grid>().is_err(), "accepted:\n{}", text);
+        }
+    }
+}
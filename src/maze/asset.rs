@@ -0,0 +1,99 @@
+use super::Grid;
+use bevy::asset::{AssetLoader, BoxedFuture, Error, LoadContext, LoadedAsset};
+use bevy::reflect::{TypePath, TypeUuid};
+use serde::Deserialize;
+
+// A hand-made level, e.g. assets/levels/example.maze.ron
+#[derive(TypeUuid, TypePath)]
+#[uuid = "4b50bb88-1aec-4065-92b7-5280e54fb1c5"]
+pub struct MazeAsset {
+    pub grid: Grid,
+    pub decorations: Vec<Decoration>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Decoration {
+    // glTF file inside assets/, e.g. "cannon.glb"
+    pub model: String,
+    pub cell: (usize, usize),
+    // Degrees around the vertical axis
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "Decoration::default_scale")]
+    pub scale: f32,
+}
+
+impl Decoration {
+    fn default_scale() -> f32 {
+        1.0
+    }
+}
+
+// On-disk layout of a .maze.ron file. Walls are drawn in the ASCII format of `Grid`'s
+// Display impl, one string per line.
+#[derive(Deserialize)]
+struct MazeDefinition {
+    width: usize,
+    height: usize,
+    walls: Vec<String>,
+    start: Option<(usize, usize)>,
+    finish: Option<(usize, usize)>,
+    #[serde(default)]
+    decorations: Vec<Decoration>,
+}
+
+impl MazeDefinition {
+    fn into_asset(self) -> Result<MazeAsset, String> {
+        let mut grid: Grid = self.walls.join("\n").parse()?;
+        if grid.width != self.width || grid.height != self.height {
+            return Err(format!(
+                "walls describe a {}x{} maze, expected {}x{}",
+                grid.width, grid.height, self.width, self.height
+            ));
+        }
+
+        let in_bounds = |(row, col): (usize, usize)| row < grid.height && col < grid.width;
+        for cell in [self.start, self.finish]
+            .into_iter()
+            .flatten()
+            .chain(self.decorations.iter().map(|decoration| decoration.cell))
+        {
+            if !in_bounds(cell) {
+                return Err(format!("cell {:?} is outside of the maze", cell));
+            }
+        }
+        if let Some(start) = self.start {
+            grid.start = start;
+        }
+        if let Some(finish) = self.finish {
+            grid.finish = finish;
+        }
+
+        Ok(MazeAsset {
+            grid,
+            decorations: self.decorations,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct MazeAssetLoader;
+
+impl AssetLoader for MazeAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let definition: MazeDefinition = ron::de::from_bytes(bytes)?;
+            let asset = definition.into_asset().map_err(Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["maze.ron"]
+    }
+}
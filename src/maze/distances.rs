@@ -0,0 +1,120 @@
+use super::Grid;
+use bevy::prelude::*;
+use std::collections::VecDeque;
+
+// Dijkstra distances from a root cell. Every corridor step costs the same, so a breadth first
+// flood visits cells in the same order a priority queue would.
+#[derive(Component)]
+pub struct DistanceMap {
+    root: (usize, usize),
+    distances: Vec<Vec<Option<usize>>>,
+}
+
+impl DistanceMap {
+    pub fn new(grid: &Grid, root: (usize, usize)) -> Self {
+        let mut distances = vec![vec![None; grid.width]; grid.height];
+        let mut queue = VecDeque::from([root]);
+        distances[root.0][root.1] = Some(0);
+
+        while let Some(cell) = queue.pop_front() {
+            let distance = distances[cell.0][cell.1].unwrap() + 1;
+            for next in grid.open_neighbors(cell.0, cell.1) {
+                if distances[next.0][next.1].is_none() {
+                    distances[next.0][next.1] = Some(distance);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        DistanceMap { root, distances }
+    }
+
+    // None for cells that cannot be reached from the root
+    pub fn get(&self, (row, col): (usize, usize)) -> Option<usize> {
+        self.distances[row][col]
+    }
+
+    pub fn max(&self) -> usize {
+        self.distances
+            .iter()
+            .flatten()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn farthest(&self) -> (usize, usize) {
+        let mut farthest = (self.root, 0);
+        for (row_i, row) in self.distances.iter().enumerate() {
+            for (col_i, distance) in row.iter().enumerate() {
+                if let Some(distance) = *distance {
+                    if distance > farthest.1 {
+                        farthest = ((row_i, col_i), distance);
+                    }
+                }
+            }
+        }
+        farthest.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::solver::BreadthFirst;
+    use crate::maze::{MazeAlgorithm, MazeSolver};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn generate(algorithm: MazeAlgorithm, width: usize, height: usize, seed: u64) -> Grid {
+        let mut grid = Grid::new(width, height);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        algorithm.generator().generate(&mut grid, &mut rng);
+        grid
+    }
+
+    #[test]
+    fn distance_to_finish_matches_the_solution() {
+        for algorithm in MazeAlgorithm::ALL {
+            for seed in 0..3 {
+                let grid = generate(algorithm, 11, 7, seed);
+                let path = BreadthFirst.solve(&grid, grid.start, grid.finish).unwrap();
+                let distances = DistanceMap::new(&grid, grid.start);
+                assert_eq!(
+                    distances.get(grid.finish),
+                    Some(path.len() - 1),
+                    "{:?} seed {}",
+                    algorithm,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn farthest_twice_finds_the_longest_path() {
+        for algorithm in MazeAlgorithm::ALL {
+            for (width, height) in [(1, 1), (1, 6), (6, 1), (7, 5)] {
+                for seed in 0..3 {
+                    let grid = generate(algorithm, width, height, seed);
+                    let one_end = DistanceMap::new(&grid, grid.start).farthest();
+                    let other_end = DistanceMap::new(&grid, one_end).farthest();
+                    let found = DistanceMap::new(&grid, one_end).get(other_end).unwrap();
+
+                    let mut longest = 0;
+                    for row in 0..height {
+                        for col in 0..width {
+                            longest = longest.max(DistanceMap::new(&grid, (row, col)).max());
+                        }
+                    }
+                    assert_eq!(
+                        found, longest,
+                        "{:?} {}x{} seed {}",
+                        algorithm, width, height, seed
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,607 @@
+use super::Grid;
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+pub trait MazeGenerator {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng);
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+pub enum MazeAlgorithm {
+    #[default]
+    RecursiveBacktracker,
+    Prim,
+    Kruskal,
+    Wilson,
+    AldousBroder,
+    Eller,
+    HuntAndKill,
+    Sidewinder,
+    BinaryTree,
+    GrowingTree,
+    RecursiveDivision,
+}
+
+impl MazeAlgorithm {
+    pub const ALL: [MazeAlgorithm; 11] = [
+        MazeAlgorithm::RecursiveBacktracker,
+        MazeAlgorithm::Prim,
+        MazeAlgorithm::Kruskal,
+        MazeAlgorithm::Wilson,
+        MazeAlgorithm::AldousBroder,
+        MazeAlgorithm::Eller,
+        MazeAlgorithm::HuntAndKill,
+        MazeAlgorithm::Sidewinder,
+        MazeAlgorithm::BinaryTree,
+        MazeAlgorithm::GrowingTree,
+        MazeAlgorithm::RecursiveDivision,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MazeAlgorithm::RecursiveBacktracker => "Recursive backtracker",
+            MazeAlgorithm::Prim => "Prim",
+            MazeAlgorithm::Kruskal => "Kruskal",
+            MazeAlgorithm::Wilson => "Wilson",
+            MazeAlgorithm::AldousBroder => "Aldous-Broder",
+            MazeAlgorithm::Eller => "Eller",
+            MazeAlgorithm::HuntAndKill => "Hunt and kill",
+            MazeAlgorithm::Sidewinder => "Sidewinder",
+            MazeAlgorithm::BinaryTree => "Binary tree",
+            MazeAlgorithm::GrowingTree => "Growing tree",
+            MazeAlgorithm::RecursiveDivision => "Recursive division",
+        }
+    }
+
+    pub fn generator(&self) -> Box<dyn MazeGenerator> {
+        match self {
+            MazeAlgorithm::RecursiveBacktracker => Box::new(RecursiveBacktracker),
+            MazeAlgorithm::Prim => Box::new(Prim),
+            MazeAlgorithm::Kruskal => Box::new(Kruskal),
+            MazeAlgorithm::Wilson => Box::new(Wilson),
+            MazeAlgorithm::AldousBroder => Box::new(AldousBroder),
+            MazeAlgorithm::Eller => Box::new(Eller),
+            MazeAlgorithm::HuntAndKill => Box::new(HuntAndKill),
+            MazeAlgorithm::Sidewinder => Box::new(Sidewinder),
+            MazeAlgorithm::BinaryTree => Box::new(BinaryTree),
+            MazeAlgorithm::GrowingTree => Box::new(GrowingTree::default()),
+            MazeAlgorithm::RecursiveDivision => Box::new(RecursiveDivision),
+        }
+    }
+}
+
+// Knocks down a wall in the given fraction of dead ends, turning a perfect maze into one with
+// loops. Walls towards other dead ends are preferred so one removal can fix two of them.
+pub fn braid(grid: &mut Grid, density: f64, rng: &mut ChaCha8Rng) {
+    let is_dead_end =
+        |grid: &Grid, (row, col): (usize, usize)| grid.open_neighbors(row, col).len() == 1;
+
+    let mut dead_ends = Vec::new();
+    for row in 0..grid.height {
+        for col in 0..grid.width {
+            if is_dead_end(grid, (row, col)) {
+                dead_ends.push((row, col));
+            }
+        }
+    }
+    dead_ends.shuffle(rng);
+
+    for cell in dead_ends {
+        if !is_dead_end(grid, cell) || !rng.gen_bool(density.clamp(0.0, 1.0)) {
+            continue;
+        }
+
+        let closed: Vec<(usize, usize)> = grid
+            .neighbors(cell.0, cell.1)
+            .into_iter()
+            .filter(|&neighbor| grid.has_wall(cell, neighbor))
+            .collect();
+        let dead_end_neighbors: Vec<(usize, usize)> = closed
+            .iter()
+            .copied()
+            .filter(|&neighbor| is_dead_end(grid, neighbor))
+            .collect();
+
+        let neighbor = dead_end_neighbors
+            .choose(rng)
+            .or_else(|| closed.choose(rng))
+            .copied();
+        if let Some(neighbor) = neighbor {
+            grid.remove_wall(cell, neighbor);
+        }
+    }
+}
+
+fn random_cell(grid: &Grid, rng: &mut ChaCha8Rng) -> (usize, usize) {
+    (rng.gen_range(0..grid.height), rng.gen_range(0..grid.width))
+}
+
+pub struct RecursiveBacktracker;
+
+impl MazeGenerator for RecursiveBacktracker {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut stack = Vec::new();
+        let current_cell = (0, 0);
+
+        grid.grid[current_cell.0][current_cell.1].visited = true;
+        stack.push(current_cell);
+
+        while let Some(current_cell) = stack.pop() {
+            let neighbors = grid.check_neighbors(current_cell.0, current_cell.1);
+            if let Some(&neighbor) = neighbors.choose(rng) {
+                stack.push(current_cell);
+                grid.remove_wall(current_cell, neighbor);
+                grid.grid[neighbor.0][neighbor.1].visited = true;
+                stack.push(neighbor);
+            }
+        }
+    }
+}
+
+pub struct Prim;
+
+impl MazeGenerator for Prim {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut in_frontier = vec![vec![false; grid.width]; grid.height];
+        let mut frontier = Vec::new();
+
+        let start = random_cell(grid, rng);
+        grid.grid[start.0][start.1].visited = true;
+        for neighbor in grid.neighbors(start.0, start.1) {
+            in_frontier[neighbor.0][neighbor.1] = true;
+            frontier.push(neighbor);
+        }
+
+        while !frontier.is_empty() {
+            let cell = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            let neighbors = grid.visited_neighbors(cell.0, cell.1);
+            let neighbor = *neighbors.choose(rng).unwrap();
+
+            grid.remove_wall(cell, neighbor);
+            grid.grid[cell.0][cell.1].visited = true;
+
+            for next in grid.check_neighbors(cell.0, cell.1) {
+                if !in_frontier[next.0][next.1] {
+                    in_frontier[next.0][next.1] = true;
+                    frontier.push(next);
+                }
+            }
+        }
+    }
+}
+
+pub struct Kruskal;
+
+impl Kruskal {
+    fn find(parents: &mut [usize], mut set: usize) -> usize {
+        while parents[set] != set {
+            parents[set] = parents[parents[set]];
+            set = parents[set];
+        }
+        set
+    }
+}
+
+impl MazeGenerator for Kruskal {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut edges = Vec::new();
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                if row + 1 < grid.height {
+                    edges.push(((row, col), (row + 1, col)));
+                }
+                if col + 1 < grid.width {
+                    edges.push(((row, col), (row, col + 1)));
+                }
+            }
+        }
+        edges.shuffle(rng);
+
+        let mut parents: Vec<usize> = (0..grid.width * grid.height).collect();
+        for (a, b) in edges {
+            let set_a = Kruskal::find(&mut parents, a.0 * grid.width + a.1);
+            let set_b = Kruskal::find(&mut parents, b.0 * grid.width + b.1);
+            if set_a != set_b {
+                parents[set_a] = set_b;
+                grid.remove_wall(a, b);
+            }
+        }
+
+        for row in grid.grid.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.visited = true;
+            }
+        }
+    }
+}
+
+pub struct Wilson;
+
+impl MazeGenerator for Wilson {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let start = random_cell(grid, rng);
+        grid.grid[start.0][start.1].visited = true;
+
+        let mut next = vec![vec![None; grid.width]; grid.height];
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                if grid.grid[row][col].visited {
+                    continue;
+                }
+
+                // Random walk until the maze is hit, remembering only the last exit of each
+                // cell so that loops are erased
+                let mut cell = (row, col);
+                while !grid.grid[cell.0][cell.1].visited {
+                    let neighbor = *grid.neighbors(cell.0, cell.1).choose(rng).unwrap();
+                    next[cell.0][cell.1] = Some(neighbor);
+                    cell = neighbor;
+                }
+
+                let mut cell = (row, col);
+                while !grid.grid[cell.0][cell.1].visited {
+                    let neighbor = next[cell.0][cell.1].unwrap();
+                    grid.grid[cell.0][cell.1].visited = true;
+                    grid.remove_wall(cell, neighbor);
+                    cell = neighbor;
+                }
+            }
+        }
+    }
+}
+
+pub struct AldousBroder;
+
+impl MazeGenerator for AldousBroder {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut cell = random_cell(grid, rng);
+        grid.grid[cell.0][cell.1].visited = true;
+        let mut remaining = grid.width * grid.height - 1;
+
+        while remaining > 0 {
+            let neighbor = *grid.neighbors(cell.0, cell.1).choose(rng).unwrap();
+            if !grid.grid[neighbor.0][neighbor.1].visited {
+                grid.remove_wall(cell, neighbor);
+                grid.grid[neighbor.0][neighbor.1].visited = true;
+                remaining -= 1;
+            }
+            cell = neighbor;
+        }
+    }
+}
+
+pub struct Eller;
+
+impl MazeGenerator for Eller {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut sets: Vec<Option<usize>> = vec![None; grid.width];
+        let mut next_set = 0;
+
+        for row in 0..grid.height {
+            let last_row = row + 1 == grid.height;
+
+            for set in sets.iter_mut() {
+                if set.is_none() {
+                    *set = Some(next_set);
+                    next_set += 1;
+                }
+            }
+
+            // Join adjacent cells of different sets, always on the last row
+            for col in 0..grid.width - 1 {
+                let (left, right) = (sets[col], sets[col + 1]);
+                if left != right && (last_row || rng.gen_bool(0.5)) {
+                    grid.remove_wall((row, col), (row, col + 1));
+                    for set in sets.iter_mut() {
+                        if *set == right {
+                            *set = left;
+                        }
+                    }
+                }
+            }
+
+            for col in 0..grid.width {
+                grid.grid[row][col].visited = true;
+            }
+            if last_row {
+                break;
+            }
+
+            // Every set carries at least one cell into the next row
+            let mut next_sets = vec![None; grid.width];
+            let mut columns: Vec<usize> = (0..grid.width).collect();
+            columns.shuffle(rng);
+            for col in columns {
+                let set = sets[col];
+                let carried = next_sets.contains(&set);
+                if !carried || rng.gen_bool(0.5) {
+                    grid.remove_wall((row, col), (row + 1, col));
+                    next_sets[col] = set;
+                }
+            }
+            sets = next_sets;
+        }
+    }
+}
+
+pub struct HuntAndKill;
+
+impl HuntAndKill {
+    fn hunt(grid: &Grid, rng: &mut ChaCha8Rng) -> Option<((usize, usize), (usize, usize))> {
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                if grid.grid[row][col].visited {
+                    continue;
+                }
+                if let Some(&neighbor) = grid.visited_neighbors(row, col).choose(rng) {
+                    return Some(((row, col), neighbor));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl MazeGenerator for HuntAndKill {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let mut cell = random_cell(grid, rng);
+        grid.grid[cell.0][cell.1].visited = true;
+
+        loop {
+            let neighbors = grid.check_neighbors(cell.0, cell.1);
+            if let Some(&neighbor) = neighbors.choose(rng) {
+                grid.remove_wall(cell, neighbor);
+                grid.grid[neighbor.0][neighbor.1].visited = true;
+                cell = neighbor;
+            } else if let Some((unvisited, neighbor)) = HuntAndKill::hunt(grid, rng) {
+                grid.remove_wall(unvisited, neighbor);
+                grid.grid[unvisited.0][unvisited.1].visited = true;
+                cell = unvisited;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+pub struct Sidewinder;
+
+impl MazeGenerator for Sidewinder {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        for row in 0..grid.height {
+            let at_top = row + 1 == grid.height;
+            let mut run_start = 0;
+
+            for col in 0..grid.width {
+                grid.grid[row][col].visited = true;
+                let at_east = col + 1 == grid.width;
+
+                if !at_east && (at_top || rng.gen_bool(0.5)) {
+                    grid.remove_wall((row, col), (row, col + 1));
+                } else {
+                    if !at_top {
+                        let carve_col = rng.gen_range(run_start..=col);
+                        grid.remove_wall((row, carve_col), (row + 1, carve_col));
+                    }
+                    run_start = col + 1;
+                }
+            }
+        }
+    }
+}
+
+pub struct BinaryTree;
+
+impl MazeGenerator for BinaryTree {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                grid.grid[row][col].visited = true;
+
+                let mut options = Vec::new();
+                if row + 1 < grid.height {
+                    options.push((row + 1, col));
+                }
+                if col + 1 < grid.width {
+                    options.push((row, col + 1));
+                }
+                if let Some(&neighbor) = options.choose(rng) {
+                    grid.remove_wall((row, col), neighbor);
+                }
+            }
+        }
+    }
+}
+
+pub struct GrowingTree {
+    // Chance of continuing from the newest active cell instead of a random one, 1.0 behaves
+    // like the recursive backtracker and 0.0 like Prim's
+    pub newest_bias: f64,
+}
+
+impl Default for GrowingTree {
+    fn default() -> Self {
+        GrowingTree { newest_bias: 0.5 }
+    }
+}
+
+impl MazeGenerator for GrowingTree {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        let start = random_cell(grid, rng);
+        grid.grid[start.0][start.1].visited = true;
+        let mut active = vec![start];
+
+        while !active.is_empty() {
+            let index = if rng.gen_bool(self.newest_bias) {
+                active.len() - 1
+            } else {
+                rng.gen_range(0..active.len())
+            };
+            let cell = active[index];
+
+            let neighbors = grid.check_neighbors(cell.0, cell.1);
+            if let Some(&neighbor) = neighbors.choose(rng) {
+                grid.remove_wall(cell, neighbor);
+                grid.grid[neighbor.0][neighbor.1].visited = true;
+                active.push(neighbor);
+            } else {
+                active.remove(index);
+            }
+        }
+    }
+}
+
+pub struct RecursiveDivision;
+
+impl MazeGenerator for RecursiveDivision {
+    fn generate(&self, grid: &mut Grid, rng: &mut ChaCha8Rng) {
+        grid.clear_walls();
+        for row in grid.grid.iter_mut() {
+            for cell in row.iter_mut() {
+                cell.visited = true;
+            }
+        }
+
+        // (row, col, height, width) of the chambers still to be divided
+        let mut chambers = vec![(0, 0, grid.height, grid.width)];
+        while let Some((row, col, height, width)) = chambers.pop() {
+            if height < 2 || width < 2 {
+                continue;
+            }
+
+            let horizontal = if height == width {
+                rng.gen_bool(0.5)
+            } else {
+                height > width
+            };
+
+            if horizontal {
+                let wall_row = rng.gen_range(row..row + height - 1);
+                let gap = rng.gen_range(col..col + width);
+                for wall_col in col..col + width {
+                    if wall_col != gap {
+                        grid.add_wall((wall_row, wall_col), (wall_row + 1, wall_col));
+                    }
+                }
+                chambers.push((row, col, wall_row - row + 1, width));
+                chambers.push((wall_row + 1, col, row + height - wall_row - 1, width));
+            } else {
+                let wall_col = rng.gen_range(col..col + width - 1);
+                let gap = rng.gen_range(row..row + height);
+                for wall_row in row..row + height {
+                    if wall_row != gap {
+                        grid.add_wall((wall_row, wall_col), (wall_row, wall_col + 1));
+                    }
+                }
+                chambers.push((row, col, height, wall_col - col + 1));
+                chambers.push((row, wall_col + 1, height, col + width - wall_col - 1));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const SIZES: [(usize, usize); 6] = [(1, 1), (1, 7), (7, 1), (2, 2), (5, 9), (16, 16)];
+
+    fn generate(algorithm: MazeAlgorithm, width: usize, height: usize, seed: u64) -> Grid {
+        let mut grid = Grid::new(width, height);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        algorithm.generator().generate(&mut grid, &mut rng);
+        grid
+    }
+
+    fn walls(grid: &Grid) -> Vec<[bool; 2]> {
+        grid.grid.iter().flatten().map(|cell| cell.walls).collect()
+    }
+
+    #[test]
+    fn generates_perfect_mazes() {
+        for algorithm in MazeAlgorithm::ALL {
+            for (width, height) in SIZES {
+                for seed in 0..5 {
+                    let grid = generate(algorithm, width, height, seed);
+                    let name = format!("{:?} {}x{} seed {}", algorithm, width, height, seed);
+
+                    let mut open_walls = 0;
+                    for row in 0..height {
+                        for col in 0..width {
+                            let [north, east] = grid.grid[row][col].walls;
+                            if row + 1 == height {
+                                assert!(north, "{}: open outer wall", name);
+                            } else if !north {
+                                open_walls += 1;
+                            }
+                            if col + 1 == width {
+                                assert!(east, "{}: open outer wall", name);
+                            } else if !east {
+                                open_walls += 1;
+                            }
+                        }
+                    }
+                    // A spanning tree, so no loops
+                    assert_eq!(open_walls, width * height - 1, "{}", name);
+
+                    let mut seen = vec![vec![false; width]; height];
+                    seen[0][0] = true;
+                    let mut stack = vec![(0, 0)];
+                    let mut reached = 0;
+                    while let Some((row, col)) = stack.pop() {
+                        reached += 1;
+                        for (r, c) in grid.open_neighbors(row, col) {
+                            if !seen[r][c] {
+                                seen[r][c] = true;
+                                stack.push((r, c));
+                            }
+                        }
+                    }
+                    assert_eq!(reached, width * height, "{}: unreachable cells", name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn full_braid_removes_every_dead_end() {
+        for algorithm in MazeAlgorithm::ALL {
+            // The ends of a one cell wide corridor have nowhere else to open up to
+            for (width, height) in [(2, 2), (2, 7), (7, 2), (5, 9), (16, 16)] {
+                for seed in 0..3 {
+                    let mut grid = generate(algorithm, width, height, seed);
+                    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                    braid(&mut grid, 1.0, &mut rng);
+                    for row in 0..height {
+                        for col in 0..width {
+                            assert!(
+                                grid.open_neighbors(row, col).len() >= 2,
+                                "{:?} {}x{} seed {}: dead end at {:?}",
+                                algorithm,
+                                width,
+                                height,
+                                seed,
+                                (row, col)
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_maze() {
+        for algorithm in MazeAlgorithm::ALL {
+            let first = generate(algorithm, 12, 8, 42);
+            let second = generate(algorithm, 12, 8, 42);
+            assert_eq!(walls(&first), walls(&second), "{:?}", algorithm);
+            assert_eq!(first.start, second.start, "{:?}", algorithm);
+            assert_eq!(first.finish, second.finish, "{:?}", algorithm);
+        }
+    }
+}
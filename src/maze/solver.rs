@@ -0,0 +1,332 @@
+use super::Grid;
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+pub trait MazeSolver {
+    fn solve(
+        &self,
+        grid: &Grid,
+        start: (usize, usize),
+        finish: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect)]
+pub enum SolverStrategy {
+    #[default]
+    BreadthFirst,
+    AStar,
+    DeadEndFilling,
+    LeftWallFollower,
+    RightWallFollower,
+}
+
+impl SolverStrategy {
+    pub fn solver(&self) -> Box<dyn MazeSolver> {
+        match self {
+            SolverStrategy::BreadthFirst => Box::new(BreadthFirst),
+            SolverStrategy::AStar => Box::new(AStar),
+            SolverStrategy::DeadEndFilling => Box::new(DeadEndFilling),
+            SolverStrategy::LeftWallFollower => Box::new(WallFollower { left_hand: true }),
+            SolverStrategy::RightWallFollower => Box::new(WallFollower { left_hand: false }),
+        }
+    }
+}
+
+// Walks the parent links back from the finish, returning the path in start to finish order
+fn trace_path(
+    parents: &[Vec<Option<(usize, usize)>>],
+    start: (usize, usize),
+    finish: (usize, usize),
+) -> Vec<(usize, usize)> {
+    let mut path = vec![finish];
+    let mut cell = finish;
+    while cell != start {
+        cell = parents[cell.0][cell.1].unwrap();
+        path.push(cell);
+    }
+    path.reverse();
+    path
+}
+
+fn breadth_first(
+    grid: &Grid,
+    start: (usize, usize),
+    finish: (usize, usize),
+    blocked: &[Vec<bool>],
+) -> Option<Vec<(usize, usize)>> {
+    let mut parents = vec![vec![None; grid.width]; grid.height];
+    let mut seen = vec![vec![false; grid.width]; grid.height];
+    let mut queue = VecDeque::from([start]);
+    seen[start.0][start.1] = true;
+
+    while let Some(cell) = queue.pop_front() {
+        if cell == finish {
+            return Some(trace_path(&parents, start, finish));
+        }
+        for next in grid.open_neighbors(cell.0, cell.1) {
+            if !seen[next.0][next.1] && !blocked[next.0][next.1] {
+                seen[next.0][next.1] = true;
+                parents[next.0][next.1] = Some(cell);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+pub struct BreadthFirst;
+
+impl MazeSolver for BreadthFirst {
+    fn solve(
+        &self,
+        grid: &Grid,
+        start: (usize, usize),
+        finish: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        let blocked = vec![vec![false; grid.width]; grid.height];
+        breadth_first(grid, start, finish, &blocked)
+    }
+}
+
+pub struct AStar;
+
+impl MazeSolver for AStar {
+    fn solve(
+        &self,
+        grid: &Grid,
+        start: (usize, usize),
+        finish: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        let heuristic =
+            |cell: (usize, usize)| cell.0.abs_diff(finish.0) + cell.1.abs_diff(finish.1);
+
+        let mut parents = vec![vec![None; grid.width]; grid.height];
+        let mut costs = vec![vec![usize::MAX; grid.width]; grid.height];
+        let mut open = BinaryHeap::from([Reverse((heuristic(start), start))]);
+        costs[start.0][start.1] = 0;
+
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == finish {
+                return Some(trace_path(&parents, start, finish));
+            }
+            let cost = costs[cell.0][cell.1] + 1;
+            for next in grid.open_neighbors(cell.0, cell.1) {
+                if cost < costs[next.0][next.1] {
+                    costs[next.0][next.1] = cost;
+                    parents[next.0][next.1] = Some(cell);
+                    open.push(Reverse((cost + heuristic(next), next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+pub struct DeadEndFilling;
+
+impl MazeSolver for DeadEndFilling {
+    fn solve(
+        &self,
+        grid: &Grid,
+        start: (usize, usize),
+        finish: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut filled = vec![vec![false; grid.width]; grid.height];
+        let open_count = |filled: &Vec<Vec<bool>>, cell: (usize, usize)| {
+            grid.open_neighbors(cell.0, cell.1)
+                .into_iter()
+                .filter(|next| !filled[next.0][next.1])
+                .count()
+        };
+
+        let mut dead_ends = Vec::new();
+        for row in 0..grid.height {
+            for col in 0..grid.width {
+                dead_ends.push((row, col));
+            }
+        }
+
+        // Fill every dead end and follow the corridor it leads into until a junction
+        while let Some(cell) = dead_ends.pop() {
+            if cell == start || cell == finish || filled[cell.0][cell.1] {
+                continue;
+            }
+            if open_count(&filled, cell) <= 1 {
+                filled[cell.0][cell.1] = true;
+                dead_ends.extend(grid.open_neighbors(cell.0, cell.1));
+            }
+        }
+
+        // Only the solution corridors are left, loops included in braided mazes
+        breadth_first(grid, start, finish, &filled)
+    }
+}
+
+pub struct WallFollower {
+    pub left_hand: bool,
+}
+
+impl WallFollower {
+    // North, East, South, West
+    const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    fn step(grid: &Grid, cell: (usize, usize), direction: usize) -> Option<(usize, usize)> {
+        let (d_row, d_col) = WallFollower::DIRECTIONS[direction];
+        let next = (
+            cell.0.checked_add_signed(d_row)?,
+            cell.1.checked_add_signed(d_col)?,
+        );
+        if next.0 < grid.height && next.1 < grid.width && !grid.has_wall(cell, next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+}
+
+impl MazeSolver for WallFollower {
+    fn solve(
+        &self,
+        grid: &Grid,
+        start: (usize, usize),
+        finish: (usize, usize),
+    ) -> Option<Vec<(usize, usize)>> {
+        // Preferred turns relative to the current heading: hand side, ahead, other side, back
+        let turns = if self.left_hand {
+            [3, 0, 1, 2]
+        } else {
+            [1, 0, 3, 2]
+        };
+
+        let mut path = vec![start];
+        let mut cell = start;
+        let mut direction = 0;
+        // Every (cell, heading) state can only be visited once before the walk repeats itself,
+        // which happens when the finish sits on a loop detached from the start's wall
+        for _ in 0..=grid.width * grid.height * 4 {
+            if cell == finish {
+                return Some(path);
+            }
+
+            let (next_direction, next) = turns
+                .iter()
+                .map(|turn| (direction + turn) % 4)
+                .find_map(|dir| WallFollower::step(grid, cell, dir).map(|next| (dir, next)))?;
+            direction = next_direction;
+            cell = next;
+
+            // Erase the loops and dead ends the walk backed out of
+            if let Some(index) = path.iter().position(|&visited| visited == cell) {
+                path.truncate(index + 1);
+            } else {
+                path.push(cell);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maze::{braid, MazeAlgorithm};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const STRATEGIES: [SolverStrategy; 5] = [
+        SolverStrategy::BreadthFirst,
+        SolverStrategy::AStar,
+        SolverStrategy::DeadEndFilling,
+        SolverStrategy::LeftWallFollower,
+        SolverStrategy::RightWallFollower,
+    ];
+    const SIZES: [(usize, usize); 5] = [(1, 1), (1, 6), (6, 1), (5, 9), (14, 14)];
+
+    fn generate(algorithm: MazeAlgorithm, width: usize, height: usize, seed: u64) -> Grid {
+        let mut grid = Grid::new(width, height);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        algorithm.generator().generate(&mut grid, &mut rng);
+        grid
+    }
+
+    fn assert_valid_path(grid: &Grid, path: &[(usize, usize)], name: &str) {
+        assert_eq!(path.first(), Some(&grid.start), "{}: wrong start", name);
+        assert_eq!(path.last(), Some(&grid.finish), "{}: wrong finish", name);
+        for step in path.windows(2) {
+            assert!(
+                grid.open_neighbors(step[0].0, step[0].1).contains(&step[1]),
+                "{}: steps through a wall from {:?} to {:?}",
+                name,
+                step[0],
+                step[1]
+            );
+        }
+    }
+
+    #[test]
+    fn solves_perfect_mazes_with_the_shortest_path() {
+        for algorithm in MazeAlgorithm::ALL {
+            for (width, height) in SIZES {
+                for seed in 0..3 {
+                    let grid = generate(algorithm, width, height, seed);
+                    let shortest = BreadthFirst.solve(&grid, grid.start, grid.finish).unwrap();
+                    for strategy in STRATEGIES {
+                        let name = format!(
+                            "{:?} on {:?} {}x{} seed {}",
+                            strategy, algorithm, width, height, seed
+                        );
+                        let path = strategy
+                            .solver()
+                            .solve(&grid, grid.start, grid.finish)
+                            .unwrap_or_else(|| panic!("{}: no path", name));
+                        assert_valid_path(&grid, &path, &name);
+                        assert_eq!(path.len(), shortest.len(), "{}: not the shortest", name);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn solves_braided_mazes() {
+        for algorithm in MazeAlgorithm::ALL {
+            for (width, height) in SIZES {
+                for seed in 0..3 {
+                    let mut grid = generate(algorithm, width, height, seed);
+                    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                    braid(&mut grid, 0.5, &mut rng);
+                    let breadth_first = BreadthFirst.solve(&grid, grid.start, grid.finish);
+                    let a_star = AStar.solve(&grid, grid.start, grid.finish);
+                    assert_eq!(
+                        breadth_first.as_ref().map(Vec::len),
+                        a_star.as_ref().map(Vec::len),
+                        "{:?} {}x{} seed {}",
+                        algorithm,
+                        width,
+                        height,
+                        seed
+                    );
+
+                    for strategy in STRATEGIES {
+                        let name = format!(
+                            "{:?} on braided {:?} {}x{} seed {}",
+                            strategy, algorithm, width, height, seed
+                        );
+                        let path = strategy.solver().solve(&grid, grid.start, grid.finish);
+                        // Wall followers can circle a loop that does not touch the outer wall
+                        let may_fail = matches!(
+                            strategy,
+                            SolverStrategy::LeftWallFollower | SolverStrategy::RightWallFollower
+                        );
+                        match path {
+                            Some(path) => assert_valid_path(&grid, &path, &name),
+                            None => assert!(may_fail, "{}: no path", name),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
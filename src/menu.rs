@@ -0,0 +1,771 @@
+use bevy::app::AppExit;
+use bevy::asset::FileAssetIo;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::actions::{Action, Actions, Bindings};
+use crate::camera::CameraSettings;
+use crate::leaderboard::{format_times, Leaderboard, LeaderboardKey};
+use crate::maze::{MazeAlgorithm, MazeConfig, RegenerateMaze};
+use crate::state::{despawn_screen, AppState};
+
+const MIN_SIZE: usize = 2;
+const MAX_SIZE: usize = 100;
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+const EDITING_FIELD: Color = Color::rgb(0.2, 0.2, 0.45);
+
+#[derive(Component)]
+struct MainMenuScreen;
+
+#[derive(Component)]
+struct PauseMenuScreen;
+
+#[derive(Component)]
+struct SettingsScreen;
+
+// Returns to the main menu from the end of run screen
+#[derive(Component)]
+pub struct MainMenuButton;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    WidthDown,
+    WidthUp,
+    HeightDown,
+    HeightUp,
+    AlgorithmPrevious,
+    AlgorithmNext,
+    LevelPrevious,
+    LevelNext,
+    SeedField,
+    SeedRandom,
+    Play,
+}
+
+#[derive(Component, Clone, Copy)]
+enum PauseButton {
+    Resume,
+    Restart,
+    NewMaze,
+    Settings,
+    MainMenu,
+    Quit,
+    SensitivityDown,
+    SensitivityUp,
+    SmoothingDown,
+    SmoothingUp,
+    InvertY,
+    PitchLimitDown,
+    PitchLimitUp,
+    SpeedDown,
+    SpeedUp,
+    FollowDistanceDown,
+    FollowDistanceUp,
+    TransitionDown,
+    TransitionUp,
+    StickSensitivityDown,
+    StickSensitivityUp,
+    DeadZoneDown,
+    DeadZoneUp,
+    KeyboardLayout,
+    Back,
+}
+
+// Text showing the current value of a setting
+#[derive(Component, Clone, Copy)]
+enum MenuValue {
+    Width,
+    Height,
+    Algorithm,
+    Level,
+    Seed,
+    BestTimes,
+}
+
+#[derive(Component, Clone, Copy)]
+enum SettingValue {
+    Sensitivity,
+    Smoothing,
+    InvertY,
+    PitchLimit,
+    Speed,
+    FollowDistance,
+    Transition,
+    StickSensitivity,
+    DeadZone,
+    KeyboardLayout,
+}
+
+// Asset paths of the .maze.ron files in assets/levels, found when the main menu opens
+#[derive(Resource, Default)]
+struct Levels(Vec<String>);
+
+fn find_levels(mut levels: ResMut<Levels>) {
+    // Same folder the asset server loads from, which is not always the working directory
+    let dir = FileAssetIo::get_base_path().join("assets").join("levels");
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        levels.0.clear();
+        return;
+    };
+    levels.0 = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".maze.ron"))
+        .map(|name| format!("levels/{}", name))
+        .collect();
+    levels.0.sort();
+}
+
+// Seed typed into the menu, empty picks a random one when the game starts
+#[derive(Resource, Default)]
+struct SeedInput {
+    text: String,
+    editing: bool,
+}
+
+fn main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraCode-Bold.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                ..default()
+            },
+            MainMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Maze",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 80.0,
+                        color: Color::YELLOW,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(30.0)),
+                    ..default()
+                }),
+            );
+
+            setting_row(parent, &font, "Width", |row| {
+                button(row, &font, "-", MenuButton::WidthDown);
+                value(row, &font, MenuValue::Width);
+                button(row, &font, "+", MenuButton::WidthUp);
+            });
+            setting_row(parent, &font, "Height", |row| {
+                button(row, &font, "-", MenuButton::HeightDown);
+                value(row, &font, MenuValue::Height);
+                button(row, &font, "+", MenuButton::HeightUp);
+            });
+            setting_row(parent, &font, "Algorithm", |row| {
+                button(row, &font, "<", MenuButton::AlgorithmPrevious);
+                value(row, &font, MenuValue::Algorithm);
+                button(row, &font, ">", MenuButton::AlgorithmNext);
+            });
+            setting_row(parent, &font, "Level", |row| {
+                button(row, &font, "<", MenuButton::LevelPrevious);
+                value(row, &font, MenuValue::Level);
+                button(row, &font, ">", MenuButton::LevelNext);
+            });
+            setting_row(parent, &font, "Seed", |row| {
+                row.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(320.0),
+                            padding: UiRect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            ..default()
+                        },
+                        background_color: NORMAL_BUTTON.into(),
+                        ..default()
+                    },
+                    MenuButton::SeedField,
+                ))
+                .with_children(|field| {
+                    field.spawn((text(&font, "", 30.0), MenuValue::Seed));
+                });
+                button(row, &font, "Random", MenuButton::SeedRandom);
+            });
+
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            margin: UiRect::top(Val::Px(30.0)),
+                            padding: UiRect::axes(Val::Px(40.0), Val::Px(10.0)),
+                            ..default()
+                        },
+                        background_color: NORMAL_BUTTON.into(),
+                        ..default()
+                    },
+                    MenuButton::Play,
+                ))
+                .with_children(|button| {
+                    button.spawn(text(&font, "Play", 50.0));
+                });
+
+            parent.spawn((
+                text(&font, "", 20.0).with_style(Style {
+                    margin: UiRect::top(Val::Px(20.0)),
+                    ..default()
+                }),
+                MenuValue::BestTimes,
+            ));
+        });
+}
+
+fn text(font: &Handle<Font>, value: &str, font_size: f32) -> TextBundle {
+    TextBundle::from_section(
+        value,
+        TextStyle {
+            font: font.clone(),
+            font_size,
+            color: Color::WHITE,
+        },
+    )
+}
+
+fn setting_row(
+    parent: &mut ChildBuilder,
+    font: &Handle<Font>,
+    label: &str,
+    spawn_controls: impl FnOnce(&mut ChildBuilder),
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn(text(font, label, 30.0).with_style(Style {
+                width: Val::Px(200.0),
+                ..default()
+            }));
+            spawn_controls(row);
+        });
+}
+
+pub fn button(parent: &mut ChildBuilder, font: &Handle<Font>, label: &str, action: impl Component) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    min_width: Val::Px(50.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..default()
+            },
+            action,
+        ))
+        .with_children(|button| {
+            button.spawn(text(font, label, 30.0));
+        });
+}
+
+fn value(parent: &mut ChildBuilder, font: &Handle<Font>, value: impl Component) {
+    parent.spawn((
+        text(font, "", 30.0).with_style(Style {
+            width: Val::Px(320.0),
+            ..default()
+        }),
+        value,
+    ));
+}
+
+fn button_colors(
+    seed_input: Res<SeedInput>,
+    mut buttons: Query<(&Interaction, Option<&MenuButton>, &mut BackgroundColor), With<Button>>,
+) {
+    for (interaction, action, mut color) in &mut buttons {
+        *color = match interaction {
+            Interaction::Pressed => PRESSED_BUTTON,
+            Interaction::Hovered => HOVERED_BUTTON,
+            Interaction::None
+                if matches!(action, Some(MenuButton::SeedField)) && seed_input.editing =>
+            {
+                EDITING_FIELD
+            }
+            Interaction::None => NORMAL_BUTTON,
+        }
+        .into();
+    }
+}
+
+fn menu_action(
+    buttons: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+    mut config: ResMut<MazeConfig>,
+    mut seed_input: ResMut<SeedInput>,
+    mut next_state: ResMut<NextState<AppState>>,
+    levels: Res<Levels>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        // Clicking anything but the seed field stops editing it
+        seed_input.editing = matches!(action, MenuButton::SeedField);
+
+        let algorithm = MazeAlgorithm::ALL
+            .iter()
+            .position(|algorithm| *algorithm == config.algorithm)
+            .unwrap_or(0);
+        // 0 is a generated maze, the level files follow
+        let choices = levels.0.len() + 1;
+        let level = config
+            .level
+            .as_ref()
+            .and_then(|path| levels.0.iter().position(|level| level == path))
+            .map_or(0, |index| index + 1);
+        let pick_level = |index: usize| (index > 0).then(|| levels.0[index - 1].clone());
+        match action {
+            MenuButton::WidthDown => config.width = config.width.saturating_sub(1).max(MIN_SIZE),
+            MenuButton::WidthUp => config.width = (config.width + 1).min(MAX_SIZE),
+            MenuButton::HeightDown => config.height = config.height.saturating_sub(1).max(MIN_SIZE),
+            MenuButton::HeightUp => config.height = (config.height + 1).min(MAX_SIZE),
+            MenuButton::AlgorithmPrevious => {
+                config.algorithm = MazeAlgorithm::ALL
+                    [(algorithm + MazeAlgorithm::ALL.len() - 1) % MazeAlgorithm::ALL.len()]
+            }
+            MenuButton::AlgorithmNext => {
+                config.algorithm = MazeAlgorithm::ALL[(algorithm + 1) % MazeAlgorithm::ALL.len()]
+            }
+            MenuButton::LevelPrevious => config.level = pick_level((level + choices - 1) % choices),
+            MenuButton::LevelNext => config.level = pick_level((level + 1) % choices),
+            MenuButton::SeedField => (),
+            MenuButton::SeedRandom => seed_input.text.clear(),
+            MenuButton::Play => start_game(&mut config, &seed_input, &mut next_state),
+        }
+    }
+}
+
+fn seed_typing(
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut config: ResMut<MazeConfig>,
+    mut seed_input: ResMut<SeedInput>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::Return) {
+        start_game(&mut config, &seed_input, &mut next_state);
+        return;
+    }
+    if !seed_input.editing {
+        characters.clear();
+        return;
+    }
+
+    for event in characters.iter() {
+        // Seeds are u64, anything that would not parse is dropped
+        if event.char.is_ascii_digit() {
+            let mut text = seed_input.text.clone();
+            text.push(event.char);
+            if text.parse::<u64>().is_ok() {
+                seed_input.text = text;
+            }
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        seed_input.text.pop();
+    }
+}
+
+fn start_game(
+    config: &mut MazeConfig,
+    seed_input: &SeedInput,
+    next_state: &mut NextState<AppState>,
+) {
+    config.seed = seed_input.text.parse().unwrap_or_else(|_| rand::random());
+    next_state.set(AppState::Loading);
+}
+
+// Times for the typed seed, or the best seeds of this size and algorithm when it is random
+fn best_times(config: &MazeConfig, seed_input: &SeedInput, leaderboard: &Leaderboard) -> String {
+    if config.level.is_some() {
+        return "Levels have no leaderboard".to_string();
+    }
+    if let Ok(seed) = seed_input.text.parse() {
+        let key = LeaderboardKey::from_config(config).map(|key| LeaderboardKey { seed, ..key });
+        let times = key.map_or(&[][..], |key| leaderboard.times(&key));
+        if times.is_empty() {
+            return "No times for this seed yet".to_string();
+        }
+        return format!("Best times\n{}", format_times(times, None));
+    }
+
+    let seeds = LeaderboardKey::from_config(config)
+        .map_or_else(Vec::new, |key| leaderboard.best_seeds(&key));
+    if seeds.is_empty() {
+        return "No times for this maze size and algorithm yet".to_string();
+    }
+    let mut lines = "Best seeds\n".to_string();
+    for (seed, time) in seeds {
+        lines += &format!("{:>20} {:>8.2}\n", seed, time.as_secs_f32());
+    }
+    lines
+}
+
+fn update_values(
+    config: Res<MazeConfig>,
+    seed_input: Res<SeedInput>,
+    leaderboard: Res<Leaderboard>,
+    mut values: Query<(&mut Text, &MenuValue)>,
+) {
+    if !config.is_changed() && !seed_input.is_changed() {
+        return;
+    }
+    for (mut text, value) in &mut values {
+        text.sections[0].value = match value {
+            MenuValue::Width => config.width.to_string(),
+            MenuValue::Height => config.height.to_string(),
+            MenuValue::Algorithm => config.algorithm.name().to_string(),
+            MenuValue::Level => match &config.level {
+                Some(path) => path.trim_start_matches("levels/").to_string(),
+                None => "Generated".to_string(),
+            },
+            MenuValue::Seed if seed_input.text.is_empty() && !seed_input.editing => {
+                "random".to_string()
+            }
+            MenuValue::Seed => seed_input.text.clone(),
+            MenuValue::BestTimes => best_times(&config, &seed_input, &leaderboard),
+        };
+    }
+}
+
+// Text values are only written on change, so fill them in once the screen exists
+fn refresh_values(mut config: ResMut<MazeConfig>) {
+    config.set_changed();
+}
+
+// Full screen column with a title, shared by the pause and settings screens
+fn overlay(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    title: &str,
+    screen: impl Component,
+) -> Entity {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            screen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 60.0,
+                        color: Color::YELLOW,
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::bottom(Val::Px(20.0)),
+                    ..default()
+                }),
+            );
+        })
+        .id()
+}
+
+fn spawn_pause_screen(commands: &mut Commands, font: &Handle<Font>) {
+    let screen = overlay(commands, font, "Paused", PauseMenuScreen);
+    commands.entity(screen).with_children(|parent| {
+        for (label, action) in [
+            ("Resume", PauseButton::Resume),
+            ("Restart", PauseButton::Restart),
+            ("New maze", PauseButton::NewMaze),
+            ("Settings", PauseButton::Settings),
+            ("Main menu", PauseButton::MainMenu),
+            ("Quit", PauseButton::Quit),
+        ] {
+            button(parent, font, label, action);
+        }
+    });
+}
+
+fn spawn_settings_screen(commands: &mut Commands, font: &Handle<Font>) {
+    let screen = overlay(commands, font, "Settings", SettingsScreen);
+    commands.entity(screen).with_children(|parent| {
+        setting_row(parent, font, "Sensitivity", |row| {
+            button(row, font, "-", PauseButton::SensitivityDown);
+            value(row, font, SettingValue::Sensitivity);
+            button(row, font, "+", PauseButton::SensitivityUp);
+        });
+        setting_row(parent, font, "Mouse smoothing", |row| {
+            button(row, font, "-", PauseButton::SmoothingDown);
+            value(row, font, SettingValue::Smoothing);
+            button(row, font, "+", PauseButton::SmoothingUp);
+        });
+        setting_row(parent, font, "Invert Y", |row| {
+            button(row, font, "<>", PauseButton::InvertY);
+            value(row, font, SettingValue::InvertY);
+        });
+        setting_row(parent, font, "Pitch limit", |row| {
+            button(row, font, "-", PauseButton::PitchLimitDown);
+            value(row, font, SettingValue::PitchLimit);
+            button(row, font, "+", PauseButton::PitchLimitUp);
+        });
+        setting_row(parent, font, "Fly speed", |row| {
+            button(row, font, "-", PauseButton::SpeedDown);
+            value(row, font, SettingValue::Speed);
+            button(row, font, "+", PauseButton::SpeedUp);
+        });
+        setting_row(parent, font, "Follow distance", |row| {
+            button(row, font, "-", PauseButton::FollowDistanceDown);
+            value(row, font, SettingValue::FollowDistance);
+            button(row, font, "+", PauseButton::FollowDistanceUp);
+        });
+        setting_row(parent, font, "Camera blend", |row| {
+            button(row, font, "-", PauseButton::TransitionDown);
+            value(row, font, SettingValue::Transition);
+            button(row, font, "+", PauseButton::TransitionUp);
+        });
+        setting_row(parent, font, "Stick look", |row| {
+            button(row, font, "-", PauseButton::StickSensitivityDown);
+            value(row, font, SettingValue::StickSensitivity);
+            button(row, font, "+", PauseButton::StickSensitivityUp);
+        });
+        setting_row(parent, font, "Dead zone", |row| {
+            button(row, font, "-", PauseButton::DeadZoneDown);
+            value(row, font, SettingValue::DeadZone);
+            button(row, font, "+", PauseButton::DeadZoneUp);
+        });
+        setting_row(parent, font, "Keyboard", |row| {
+            button(row, font, "<>", PauseButton::KeyboardLayout);
+            value(row, font, SettingValue::KeyboardLayout);
+        });
+        button(parent, font, "Back", PauseButton::Back);
+    });
+}
+
+fn pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_pause_screen(&mut commands, &asset_server.load("fonts/FiraCode-Bold.ttf"));
+}
+
+fn toggle_pause(
+    actions: Actions,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if actions.just_pressed(Action::Pause) {
+        match state.get() {
+            AppState::Playing => next_state.set(AppState::Paused),
+            AppState::Paused => next_state.set(AppState::Playing),
+            _ => (),
+        }
+    }
+}
+
+fn freeze_physics(mut rapier: ResMut<RapierConfiguration>) {
+    rapier.physics_pipeline_active = false;
+}
+
+fn resume_physics(mut rapier: ResMut<RapierConfiguration>) {
+    rapier.physics_pipeline_active = true;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_action(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &PauseButton), Changed<Interaction>>,
+    pause_screen: Query<Entity, With<PauseMenuScreen>>,
+    settings_screen: Query<Entity, With<SettingsScreen>>,
+    mut camera_settings: Query<&mut CameraSettings>,
+    mut bindings: ResMut<Bindings>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut regenerate: EventWriter<RegenerateMaze>,
+    mut exit: EventWriter<AppExit>,
+    config: Res<MazeConfig>,
+    asset_server: Res<AssetServer>,
+) {
+    for (interaction, action) in &buttons {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let mut camera_settings = camera_settings.single_mut();
+        match action {
+            PauseButton::Resume => next_state.set(AppState::Playing),
+            PauseButton::Restart => regenerate.send(RegenerateMaze {
+                seed: Some(config.seed),
+            }),
+            PauseButton::NewMaze => regenerate.send(RegenerateMaze { seed: None }),
+            PauseButton::Settings => {
+                for entity in &pause_screen {
+                    commands.entity(entity).despawn_recursive();
+                }
+                spawn_settings_screen(&mut commands, &asset_server.load("fonts/FiraCode-Bold.ttf"));
+            }
+            PauseButton::MainMenu => next_state.set(AppState::MainMenu),
+            PauseButton::Quit => exit.send(AppExit),
+            PauseButton::SensitivityDown => {
+                bindings.look.sensitivity = (bindings.look.sensitivity - 0.01).max(0.01)
+            }
+            PauseButton::SensitivityUp => bindings.look.sensitivity += 0.01,
+            PauseButton::SmoothingDown => {
+                bindings.look.smoothing = (bindings.look.smoothing - 0.01).max(0.0)
+            }
+            PauseButton::SmoothingUp => {
+                bindings.look.smoothing = (bindings.look.smoothing + 0.01).min(0.2)
+            }
+            PauseButton::InvertY => bindings.look.invert_y = !bindings.look.invert_y,
+            // Limits stay symmetric, looking straight up or down flips the view
+            PauseButton::PitchLimitDown => {
+                bindings.look.max_pitch = (bindings.look.max_pitch - 5.0).max(30.0);
+                bindings.look.min_pitch = -bindings.look.max_pitch;
+            }
+            PauseButton::PitchLimitUp => {
+                bindings.look.max_pitch = (bindings.look.max_pitch + 5.0).min(88.0);
+                bindings.look.min_pitch = -bindings.look.max_pitch;
+            }
+            PauseButton::SpeedDown => {
+                camera_settings.speed = (camera_settings.speed - 0.5).max(0.5)
+            }
+            PauseButton::SpeedUp => camera_settings.speed += 0.5,
+            PauseButton::FollowDistanceDown => {
+                camera_settings.follow_distance = (camera_settings.follow_distance - 0.5).max(1.0)
+            }
+            PauseButton::FollowDistanceUp => {
+                camera_settings.follow_distance = (camera_settings.follow_distance + 0.5).min(6.0)
+            }
+            PauseButton::TransitionDown => {
+                camera_settings.transition_duration =
+                    (camera_settings.transition_duration - 0.1).max(0.0)
+            }
+            PauseButton::TransitionUp => {
+                camera_settings.transition_duration =
+                    (camera_settings.transition_duration + 0.1).min(2.0)
+            }
+            PauseButton::StickSensitivityDown => {
+                bindings.gamepad.look_sensitivity =
+                    (bindings.gamepad.look_sensitivity - 15.0).max(15.0)
+            }
+            PauseButton::StickSensitivityUp => bindings.gamepad.look_sensitivity += 15.0,
+            PauseButton::DeadZoneDown => {
+                bindings.gamepad.dead_zone = (bindings.gamepad.dead_zone - 0.05).max(0.0)
+            }
+            PauseButton::DeadZoneUp => {
+                bindings.gamepad.dead_zone = (bindings.gamepad.dead_zone + 0.05).min(0.9)
+            }
+            PauseButton::KeyboardLayout => {
+                let layout = bindings.layout().next();
+                bindings.set_layout(layout);
+            }
+            PauseButton::Back => {
+                for entity in &settings_screen {
+                    commands.entity(entity).despawn_recursive();
+                }
+                spawn_pause_screen(&mut commands, &asset_server.load("fonts/FiraCode-Bold.ttf"));
+            }
+        }
+    }
+}
+
+fn main_menu_button(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<MainMenuButton>)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        next_state.set(AppState::MainMenu);
+    }
+}
+
+fn update_setting_values(
+    camera_settings: Query<&CameraSettings>,
+    bindings: Res<Bindings>,
+    mut values: Query<(&mut Text, &SettingValue)>,
+) {
+    let camera_settings = camera_settings.single();
+    for (mut text, value) in &mut values {
+        text.sections[0].value = match value {
+            SettingValue::Sensitivity => format!("{:.2}°", bindings.look.sensitivity),
+            SettingValue::Smoothing => format!("{:.2} s", bindings.look.smoothing),
+            SettingValue::InvertY => if bindings.look.invert_y { "On" } else { "Off" }.to_string(),
+            SettingValue::PitchLimit => format!("{:.0}°", bindings.look.max_pitch),
+            SettingValue::Speed => format!("{:.1}", camera_settings.speed),
+            SettingValue::FollowDistance => format!("{:.1}", camera_settings.follow_distance),
+            SettingValue::Transition => {
+                format!("{:.1} s", camera_settings.transition_duration)
+            }
+            SettingValue::StickSensitivity => {
+                format!("{:.0}°/s", bindings.gamepad.look_sensitivity)
+            }
+            SettingValue::DeadZone => format!("{:.2}", bindings.gamepad.dead_zone),
+            SettingValue::KeyboardLayout => bindings.layout().name().to_string(),
+        };
+    }
+}
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SeedInput>()
+            .init_resource::<Levels>()
+            .add_systems(
+                OnEnter(AppState::MainMenu),
+                (find_levels, main_menu, refresh_values),
+            )
+            .add_systems(OnExit(AppState::MainMenu), despawn_screen::<MainMenuScreen>)
+            .add_systems(
+                Update,
+                (menu_action, seed_typing, update_values)
+                    .chain()
+                    .run_if(in_state(AppState::MainMenu)),
+            )
+            .add_systems(OnEnter(AppState::Paused), (pause_menu, freeze_physics))
+            .add_systems(
+                OnExit(AppState::Paused),
+                (
+                    despawn_screen::<PauseMenuScreen>,
+                    despawn_screen::<SettingsScreen>,
+                    resume_physics,
+                ),
+            )
+            .add_systems(
+                Update,
+                (pause_action, update_setting_values).run_if(in_state(AppState::Paused)),
+            )
+            .add_systems(
+                Update,
+                main_menu_button.run_if(in_state(AppState::Finished)),
+            )
+            .add_systems(Update, (toggle_pause, button_colors));
+    }
+}
@@ -1,26 +1,148 @@
-use crate::camera::CameraSettings;
+use crate::actions::{Action, Actions};
+use crate::camera::{camera_move, CameraSettings};
+use crate::maze::{MazeAssets, MazeConfig, MazeEntity, MazeSpawned};
+use crate::state::AppState;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
-fn spawn_on_e(
+// Tunables of the kinematic character, applied to its KinematicCharacterController
+#[derive(Component, Reflect)]
+pub struct CharacterPhysics {
+    // Downwards acceleration in m/s²
+    pub gravity: f32,
+    // Height of a jump's apex above the takeoff point, walls are 1.5 m high
+    pub jump_height: f32,
+    // Seconds after walking off a ledge during which jumping is still allowed
+    pub coyote_time: f32,
+    pub max_fall_speed: f32,
+    // Ledges up to this height are climbed without jumping
+    pub step_height: f32,
+    // Steepest slope in degrees that can be walked up
+    pub max_slope: f32,
+}
+
+impl Default for CharacterPhysics {
+    fn default() -> Self {
+        CharacterPhysics {
+            gravity: 9.81,
+            jump_height: 0.6,
+            coyote_time: 0.12,
+            max_fall_speed: 20.0,
+            step_height: 0.2,
+            max_slope: 45.0,
+        }
+    }
+}
+
+const CAPSULE_RADIUS: f32 = 0.25;
+// Half the distance between the capsule's sphere centers
+const STANDING_HALF_HEIGHT: f32 = 0.25;
+const CROUCHING_HALF_HEIGHT: f32 = 0.05;
+
+#[derive(Component, Reflect)]
+pub struct PlayerMovement {
+    pub walk_speed: f32,
+    pub sprint_speed: f32,
+    pub crouch_speed: f32,
+    // Seconds of sprinting on a full pool
+    pub max_stamina: f32,
+    pub stamina: f32,
+    // Stamina regained per second while not sprinting
+    pub stamina_regen: f32,
+    // After running out, sprinting is blocked until this much stamina is back
+    pub recovery_threshold: f32,
+    pub exhausted: bool,
+    pub sprinting: bool,
+    pub crouching: bool,
+}
+
+impl Default for PlayerMovement {
+    fn default() -> Self {
+        PlayerMovement {
+            walk_speed: 2.0,
+            sprint_speed: 4.0,
+            crouch_speed: 1.0,
+            max_stamina: 3.0,
+            stamina: 3.0,
+            stamina_regen: 0.75,
+            recovery_threshold: 1.0,
+            exhausted: false,
+            sprinting: false,
+            crouching: false,
+        }
+    }
+}
+
+impl PlayerMovement {
+    pub fn speed(&self) -> f32 {
+        if self.crouching {
+            self.crouch_speed
+        } else if self.sprinting {
+            self.sprint_speed
+        } else {
+            self.walk_speed
+        }
+    }
+}
+
+#[derive(Resource)]
+struct PlayerMeshes {
+    standing: Handle<Mesh>,
+    crouching: Handle<Mesh>,
+}
+
+fn capsule_mesh(half_height: f32) -> Mesh {
+    Mesh::from(shape::Capsule {
+        radius: CAPSULE_RADIUS,
+        depth: half_height * 2.0,
+        ..default()
+    })
+}
+
+fn capsule_collider(half_height: f32) -> Collider {
+    Collider::capsule(
+        Vec3::new(0.0, -half_height, 0.0),
+        Vec3::new(0.0, half_height, 0.0),
+        CAPSULE_RADIUS,
+    )
+}
+
+// Marks the player character. Its orientation is independent of the camera's, so models
+// attached to it face where the player looks even when the camera is elsewhere.
+#[derive(Component, Default)]
+pub struct Player {
+    // Radians around the Y axis, synced from the camera while it controls the player
+    pub yaw: f32,
+}
+
+#[derive(Component, Default)]
+pub struct CharacterMotion {
+    pub vertical_velocity: f32,
+    pub grounded: bool,
+    // Seconds since the character last stood on the ground
+    since_grounded: f32,
+}
+
+fn throw_cube(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    keys: Res<Input<KeyCode>>,
+    maze_assets: Res<MazeAssets>,
+    actions: Actions,
     position: Query<&Transform, With<CameraSettings>>,
 ) {
-    if keys.just_pressed(KeyCode::Period) {
+    if actions.just_pressed(Action::ThrowCube) {
         let position = position.single();
         commands.spawn((
             PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Cube { size: 0.05 })),
-                material: materials.add(Color::rgb(0.2, 0.5, 0.5).into()),
+                mesh: maze_assets.cube.clone(),
+                material: maze_assets.projectile_material.clone(),
                 transform: *position,
                 ..default()
             },
             RigidBody::Dynamic,
             Collider::cuboid(0.05, 0.05, 0.05),
             Restitution::coefficient(0.7),
+            // Cleared with the maze they were thrown in
+            MazeEntity,
             Velocity {
                 linvel: position.forward() * 10.0,
                 ..default()
@@ -29,32 +151,25 @@ fn spawn_on_e(
     }
 }
 
-fn spawn_on_q(
+fn throw_ball(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    keys: Res<Input<KeyCode>>,
+    maze_assets: Res<MazeAssets>,
+    actions: Actions,
     position: Query<&Transform, With<CameraSettings>>,
 ) {
-    if keys.just_pressed(KeyCode::Semicolon) {
+    if actions.just_pressed(Action::ThrowBall) {
         let position = position.single();
         commands.spawn((
             PbrBundle {
-                mesh: meshes.add(
-                    shape::Icosphere {
-                        radius: 0.25,
-                        ..default()
-                    }
-                    .try_into()
-                    .unwrap(),
-                ),
-                material: materials.add(Color::rgb(0.2, 0.5, 0.5).into()),
-                transform: position.clone(),
+                mesh: maze_assets.ball.clone(),
+                material: maze_assets.projectile_material.clone(),
+                transform: *position,
                 ..default()
             },
             RigidBody::Dynamic,
             Collider::ball(0.25),
             Restitution::coefficient(0.7),
+            MazeEntity,
             Velocity {
                 linvel: position.forward() * 10.0,
                 ..default()
@@ -68,35 +183,216 @@ fn setup_player(
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    let mut transform = Transform::from_xyz(0.5, 0.5, 0.5);
-    transform.rotate_local_y(f32::to_radians(180.0));
+    // Moved to the start room by reset_player once a maze is spawned
+    let transform = Transform::from_xyz(0.5, 0.5, 0.5);
+    let player_meshes = PlayerMeshes {
+        standing: meshes.add(capsule_mesh(STANDING_HALF_HEIGHT)),
+        crouching: meshes.add(capsule_mesh(CROUCHING_HALF_HEIGHT)),
+    };
     // Player
     commands.spawn((
         PbrBundle {
-            mesh: meshes.add(
-                shape::Capsule {
-                    radius: 0.25,
-                    depth: 0.5,
-                    ..default()
-                }
-                .try_into()
-                .unwrap(),
-            ),
+            mesh: player_meshes.standing.clone(),
             material: materials.add(Color::rgb(0.2, 0.5, 0.5).into()),
             transform,
             ..default()
         },
         RigidBody::KinematicPositionBased,
-        Collider::capsule(Vec3::new(0.0, -0.25, 0.0), Vec3::new(0.0, 0.25, 0.0), 0.25),
-        KinematicCharacterController::default(),
+        capsule_collider(STANDING_HALF_HEIGHT),
+        KinematicCharacterController {
+            snap_to_ground: Some(CharacterLength::Absolute(0.2)),
+            ..default()
+        },
+        Player::default(),
+        CharacterPhysics::default(),
+        CharacterMotion::default(),
+        PlayerMovement::default(),
     ));
+    commands.insert_resource(player_meshes);
+}
+
+// Picks walk, sprint or crouch speed from the input and updates the stamina pool
+fn update_movement(
+    mut player: Query<(
+        &mut PlayerMovement,
+        &mut Transform,
+        &mut Collider,
+        &mut Handle<Mesh>,
+    )>,
+    camera: Query<&CameraSettings>,
+    player_meshes: Res<PlayerMeshes>,
+    actions: Actions,
+    time: Res<Time>,
+) {
+    let controls_player = camera.iter().any(|camera| camera.controls_player());
+    let moving = actions.move_stick() != Vec2::ZERO
+        || [
+            Action::MoveForward,
+            Action::MoveBackward,
+            Action::StrafeLeft,
+            Action::StrafeRight,
+        ]
+        .into_iter()
+        .any(|action| actions.pressed(action));
+
+    for (mut movement, mut transform, mut collider, mut mesh) in &mut player {
+        let crouch = controls_player && actions.pressed(Action::Crouch);
+        if crouch != movement.crouching {
+            movement.crouching = crouch;
+            // Keep the feet in place, the capsule shrinks and grows from the top
+            let drop = STANDING_HALF_HEIGHT - CROUCHING_HALF_HEIGHT;
+            if crouch {
+                transform.translation.y -= drop;
+                *collider = capsule_collider(CROUCHING_HALF_HEIGHT);
+                *mesh = player_meshes.crouching.clone();
+            } else {
+                transform.translation.y += drop;
+                *collider = capsule_collider(STANDING_HALF_HEIGHT);
+                *mesh = player_meshes.standing.clone();
+            }
+        }
+
+        movement.sprinting = controls_player
+            && moving
+            && actions.pressed(Action::Sprint)
+            && !movement.crouching
+            && !movement.exhausted;
+        let delta = time.delta_seconds();
+        if movement.sprinting {
+            movement.stamina -= delta;
+            if movement.stamina <= 0.0 {
+                movement.stamina = 0.0;
+                movement.exhausted = true;
+            }
+        } else {
+            movement.stamina =
+                (movement.stamina + movement.stamina_regen * delta).min(movement.max_stamina);
+            if movement.stamina >= movement.recovery_threshold {
+                movement.exhausted = false;
+            }
+        }
+    }
+}
+
+fn apply_character_physics(
+    mut player: Query<
+        (&CharacterPhysics, &mut KinematicCharacterController),
+        Changed<CharacterPhysics>,
+    >,
+) {
+    for (physics, mut controller) in &mut player {
+        controller.autostep = Some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(physics.step_height),
+            min_width: CharacterLength::Absolute(0.1),
+            include_dynamic_bodies: false,
+        });
+        controller.max_slope_climb_angle = physics.max_slope.to_radians();
+        // Slide down anything a little shallower than what can be climbed
+        controller.min_slope_slide_angle = (physics.max_slope - 15.0).max(0.0).to_radians();
+    }
+}
+
+// Adds falling and jumping to the horizontal movement from camera_move
+fn apply_gravity(
+    mut player: Query<(
+        &CharacterPhysics,
+        &mut CharacterMotion,
+        &mut KinematicCharacterController,
+        Option<&KinematicCharacterControllerOutput>,
+    )>,
+    camera: Query<&CameraSettings>,
+    actions: Actions,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    for (physics, mut motion, mut controller, output) in &mut player {
+        motion.grounded = output.is_some_and(|output| output.grounded);
+        if motion.grounded {
+            motion.since_grounded = 0.0;
+            motion.vertical_velocity = motion.vertical_velocity.max(0.0);
+        } else {
+            motion.since_grounded += delta;
+        }
+        if let Some(output) = output {
+            // Bumped into something overhead
+            if motion.vertical_velocity > 0.0
+                && output.effective_translation.y < output.desired_translation.y * 0.5
+            {
+                motion.vertical_velocity = 0.0;
+            }
+        }
+        // Keeps pressing into the floor while grounded, so grounded detection stays stable
+        motion.vertical_velocity =
+            (motion.vertical_velocity - physics.gravity * delta).max(-physics.max_fall_speed);
+
+        let controls_player = camera.iter().any(|camera| camera.controls_player());
+        if controls_player
+            && actions.just_pressed(Action::Jump)
+            && motion.since_grounded <= physics.coyote_time
+        {
+            motion.vertical_velocity = (2.0 * physics.gravity * physics.jump_height).sqrt();
+            // No second jump from the same coyote window
+            motion.since_grounded = f32::INFINITY;
+        }
+
+        controller.translation = Some(
+            controller.translation.unwrap_or(Vec3::ZERO)
+                + Vec3::Y * motion.vertical_velocity * delta,
+        );
+    }
+}
+
+fn rotate_player(mut player: Query<(&Player, &mut Transform), Changed<Player>>) {
+    for (player, mut transform) in &mut player {
+        transform.rotation = Quat::from_rotation_y(player.yaw);
+    }
+}
+
+fn reset_player(
+    mut commands: Commands,
+    mut spawned: EventReader<MazeSpawned>,
+    mut player: Query<(
+        Entity,
+        &mut Player,
+        &mut Transform,
+        &mut CharacterMotion,
+        &mut PlayerMovement,
+    )>,
+    config: Res<MazeConfig>,
+) {
+    if let Some(spawned) = spawned.iter().last() {
+        let (entity, mut player, mut transform, mut motion, mut movement) = player.single_mut();
+        transform.translation = config.cell_position(spawned.start) + Vec3::Y * 0.5;
+        player.yaw = spawned.yaw;
+        *motion = CharacterMotion::default();
+        movement.stamina = movement.max_stamina;
+        movement.exhausted = false;
+        // The last movement of the previous run would otherwise start the new maze timer
+        commands
+            .entity(entity)
+            .remove::<KinematicCharacterControllerOutput>();
+    }
 }
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_player)
-            .add_systems(Update, (spawn_on_e, spawn_on_q));
+        app.register_type::<CharacterPhysics>()
+            .register_type::<PlayerMovement>()
+            .add_systems(Startup, setup_player)
+            .add_systems(
+                Update,
+                (
+                    throw_cube,
+                    throw_ball,
+                    apply_character_physics,
+                    update_movement.before(camera_move),
+                    apply_gravity.after(camera_move),
+                    rotate_player,
+                )
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(Update, reset_player);
     }
 }
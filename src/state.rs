@@ -0,0 +1,26 @@
+use bevy::prelude::*;
+
+#[derive(States, Default, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    Loading,
+    Playing,
+    Paused,
+    Finished,
+}
+
+// Despawns every entity tagged with T, used on OnExit to clean up a state's screens
+pub fn despawn_screen<T: Component>(mut commands: Commands, screen: Query<Entity, With<T>>) {
+    for entity in screen.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<AppState>();
+    }
+}
@@ -1,9 +1,17 @@
 use bevy::{
     diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin},
+    math::Vec3Swizzles,
     prelude::*,
 };
 
-use std::time::Instant;
+use crate::actions::{Action, Bindings};
+use crate::leaderboard::{format_times, Leaderboard, LeaderboardKey};
+use crate::maze::{CheckpointReached, MazeCompleted, MazeConfig, MazeSpawned};
+use crate::menu::{button, MainMenuButton};
+use crate::player::PlayerMovement;
+use crate::state::{despawn_screen, AppState};
+use bevy_rapier3d::prelude::*;
+use std::time::Duration;
 
 #[derive(Component)]
 struct TextChanges;
@@ -11,10 +19,24 @@ struct TextChanges;
 #[derive(Component)]
 struct MazeTimerText;
 
-#[derive(Resource)]
+#[derive(Component)]
+struct EndOfRunScreen;
+
+#[derive(Component)]
+struct StaminaBar;
+
+#[derive(Component)]
+struct StaminaFill;
+
+// Game time spent on the current maze, only advanced while playing
+#[derive(Resource, Default)]
 pub struct MazeTimer {
-    pub player_started: bool,
-    pub start_time: Option<Instant>,
+    // Set on the player's first movement
+    pub running: bool,
+    pub time: Duration,
+    // (checkpoint, time) in the order they were reached
+    pub splits: Vec<(usize, Duration)>,
+    pub finish_time: Option<Duration>,
 }
 
 fn info_text(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -60,21 +82,32 @@ fn info_text(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn((
         TextBundle::from_sections([
             TextSection::new(
-                "There should be a ",
+                "Time ",
                 TextStyle {
                     font: font.clone(),
                     font_size: 30.0,
-                    color: Color::WHITE,
+                    color: Color::YELLOW,
                 },
             ),
             TextSection::new(
-                "timer",
+                "0.00",
                 TextStyle {
                     font: font.clone(),
                     font_size: 30.0,
                     color: Color::WHITE,
                 },
             ),
+            // Difference to the best time on this maze
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: Color::WHITE,
+            }),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: Color::WHITE,
+            }),
         ])
         .with_style(Style {
             position_type: PositionType::Absolute,
@@ -85,20 +118,258 @@ fn info_text(mut commands: Commands, asset_server: Res<AssetServer>) {
         MazeTimerText,
     ));
 
-    commands.insert_resource(MazeTimer {
-        player_started: false,
-        start_time: None,
-    });
+    // Stamina bar, only shown while the pool is not full
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(20.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            StaminaBar,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(200.0),
+                        height: Val::Px(10.0),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::YELLOW.into(),
+                            ..default()
+                        },
+                        StaminaFill,
+                    ));
+                });
+        });
+}
+
+fn stamina_bar_update(
+    player: Query<&PlayerMovement, Changed<PlayerMovement>>,
+    mut bar: Query<&mut Visibility, With<StaminaBar>>,
+    mut fill: Query<(&mut Style, &mut BackgroundColor), With<StaminaFill>>,
+) {
+    let Ok(movement) = player.get_single() else {
+        return;
+    };
+    let fraction = movement.stamina / movement.max_stamina;
+    *bar.single_mut() = if fraction < 1.0 {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let (mut style, mut color) = fill.single_mut();
+    style.width = Val::Percent(fraction * 100.0);
+    // Greyed out until enough stamina is back to sprint again
+    *color = if movement.exhausted {
+        Color::GRAY.into()
+    } else {
+        Color::YELLOW.into()
+    };
+}
+
+fn start_maze_timer(
+    mut maze_timer: ResMut<MazeTimer>,
+    player: Query<&KinematicCharacterControllerOutput>,
+) {
+    if maze_timer.running || maze_timer.finish_time.is_some() {
+        return;
+    }
+    // Falling or being pushed vertically does not count as the player moving
+    let moved = player
+        .iter()
+        .any(|output| output.effective_translation.xz().length_squared() > 1e-8);
+    if moved {
+        maze_timer.running = true;
+    }
+}
+
+fn tick_maze_timer(time: Res<Time>, mut maze_timer: ResMut<MazeTimer>) {
+    if maze_timer.running && maze_timer.finish_time.is_none() {
+        maze_timer.time += time.delta();
+    }
+}
+
+fn record_split(mut reached: EventReader<CheckpointReached>, mut maze_timer: ResMut<MazeTimer>) {
+    for checkpoint in reached.iter() {
+        let recorded = maze_timer
+            .splits
+            .iter()
+            .any(|(index, _)| *index == checkpoint.index);
+        if maze_timer.running && maze_timer.finish_time.is_none() && !recorded {
+            let time = maze_timer.time;
+            maze_timer.splits.push((checkpoint.index, time));
+        }
+    }
+}
+
+fn maze_timer_update(
+    maze_timer: Res<MazeTimer>,
+    config: Res<MazeConfig>,
+    leaderboard: Res<Leaderboard>,
+    mut text: Query<&mut Text, With<MazeTimerText>>,
+) {
+    if !maze_timer.is_changed() {
+        return;
+    }
+    let mut text = text.single_mut();
+    text.sections[1].value = format!("{:.2}", maze_timer.time.as_secs_f32());
+
+    let best = LeaderboardKey::from_config(&config).and_then(|key| leaderboard.best(&key));
+    text.sections[2].value = match best {
+        Some(best) => {
+            let difference = maze_timer.time.as_secs_f32() - best.as_secs_f32();
+            text.sections[2].style.color = if difference < 0.0 {
+                Color::GREEN
+            } else {
+                Color::RED
+            };
+            format!("\nBest {:.2} ({:+.2})", best.as_secs_f32(), difference)
+        }
+        None => String::new(),
+    };
+    text.sections[3].value = maze_timer
+        .splits
+        .iter()
+        .map(|(index, time)| format!("\nCheckpoint {} {:.2}", index + 1, time.as_secs_f32()))
+        .collect();
 }
 
-fn maze_timer_update(maze_timer: Res<MazeTimer>, mut text: Query<&mut Text, With<MazeTimerText>>) {
-    if maze_timer.player_started {
-        let mut text = text.single_mut();
-        let elapsed = Instant::now().duration_since(maze_timer.start_time.unwrap());
-        text.sections[1].value = format!("{:.2}", elapsed.as_secs_f32());
+fn reset_maze_timer(mut spawned: EventReader<MazeSpawned>, mut maze_timer: ResMut<MazeTimer>) {
+    if spawned.iter().last().is_some() {
+        *maze_timer = MazeTimer::default();
     }
 }
 
+// Time of every split and the finish, with the time taken since the previous one
+fn split_breakdown(splits: &[(usize, Duration)], finish: Duration) -> String {
+    let mut breakdown = String::new();
+    let mut previous = Duration::ZERO;
+    let labelled = splits
+        .iter()
+        .map(|(index, time)| (format!("Checkpoint {}", index + 1), *time))
+        .chain([("Finish".to_string(), finish)]);
+    for (label, time) in labelled {
+        breakdown += &format!(
+            "{:<14}{:>8.2}  (+{:.2})\n",
+            label,
+            time.as_secs_f32(),
+            // A split can land a frame after the finish, since detect_sensors is not ordered
+            // against the timer systems
+            time.saturating_sub(previous).as_secs_f32()
+        );
+        previous = time;
+    }
+    breakdown
+}
+
+#[allow(clippy::too_many_arguments)]
+fn finish_run(
+    mut commands: Commands,
+    mut completed: EventReader<MazeCompleted>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut maze_timer: ResMut<MazeTimer>,
+    mut leaderboard: ResMut<Leaderboard>,
+    config: Res<MazeConfig>,
+    bindings: Res<Bindings>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(completed) = completed.iter().last() else {
+        return;
+    };
+    maze_timer.finish_time = Some(completed.time);
+    next_state.set(AppState::Finished);
+    maze_timer.time = completed.time;
+
+    let best_times = match LeaderboardKey::from_config(&config) {
+        Some(key) => {
+            let rank = leaderboard.record(key, completed.time);
+            let new_best = if rank == Some(0) { "New best!\n" } else { "" };
+            format!(
+                "\n{}Best times\n{}",
+                new_best,
+                format_times(leaderboard.times(&key), rank)
+            )
+        }
+        None => String::new(),
+    };
+
+    let new_maze_hint = bindings
+        .describe(Action::NewMaze)
+        .map_or(String::new(), |keys| {
+            format!("\nPress {} for a new maze", keys)
+        });
+
+    let font = asset_server.load("fonts/FiraCode-Bold.ttf");
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(20.0),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            EndOfRunScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_sections([
+                    TextSection::new(
+                        "Maze completed!\n",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 60.0,
+                            color: Color::YELLOW,
+                        },
+                    ),
+                    TextSection::new(
+                        format!(
+                            "Time: {:.2} s\n\n{}{}\nSeed: {}{}",
+                            completed.time.as_secs_f32(),
+                            split_breakdown(&maze_timer.splits, completed.time),
+                            best_times,
+                            completed.seed,
+                            new_maze_hint
+                        ),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 30.0,
+                            color: Color::WHITE,
+                        },
+                    ),
+                ])
+                .with_text_alignment(TextAlignment::Center),
+            );
+            button(parent, &font, "Main menu", MainMenuButton);
+        });
+}
+
 fn change_text(
     time: Res<Time>,
     diagnostics: Res<DiagnosticsStore>,
@@ -130,7 +401,21 @@ pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, info_text)
-            .add_systems(Update, (change_text, maze_timer_update));
+        app.init_resource::<MazeTimer>()
+            .add_systems(Startup, info_text)
+            .add_systems(Update, (change_text, reset_maze_timer, stamina_bar_update))
+            .add_systems(
+                Update,
+                (
+                    start_maze_timer,
+                    tick_maze_timer,
+                    record_split,
+                    maze_timer_update,
+                    finish_run,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(OnExit(AppState::Finished), despawn_screen::<EndOfRunScreen>);
     }
 }
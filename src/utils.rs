@@ -1,15 +1,10 @@
-use bevy::app::AppExit;
+use crate::actions::{Action, Actions};
+use crate::state::AppState;
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
-fn exit_on_escape(mut exit: EventWriter<AppExit>, keys: Res<Input<KeyCode>>) {
-    if keys.just_pressed(KeyCode::Escape) {
-        exit.send(AppExit);
-    }
-}
-
 fn toggle_grab_cursor(window: &mut Window) {
     if window.cursor.grab_mode == CursorGrabMode::None {
         window.cursor.grab_mode = CursorGrabMode::Locked;
@@ -20,16 +15,25 @@ fn toggle_grab_cursor(window: &mut Window) {
     }
 }
 
-fn grab_cursor(mut window: Query<&mut Window, With<PrimaryWindow>>, keys: Res<Input<KeyCode>>) {
+fn grab_cursor(mut window: Query<&mut Window, With<PrimaryWindow>>, actions: Actions) {
     let mut window = window.get_single_mut().expect("Failed to find window");
-    if keys.just_pressed(KeyCode::Tab) {
+    if actions.just_pressed(Action::GrabCursor) {
         toggle_grab_cursor(&mut window);
     }
 }
 
-fn initial_grab_cursor(mut window: Query<&mut Window, With<PrimaryWindow>>) {
+fn lock_cursor(mut window: Query<&mut Window, With<PrimaryWindow>>) {
     let mut window = window.get_single_mut().expect("Failed to find window");
-    toggle_grab_cursor(&mut window);
+    if window.cursor.grab_mode == CursorGrabMode::None {
+        toggle_grab_cursor(&mut window);
+    }
+}
+
+fn release_cursor(mut window: Query<&mut Window, With<PrimaryWindow>>) {
+    let mut window = window.get_single_mut().expect("Failed to find window");
+    if window.cursor.grab_mode != CursorGrabMode::None {
+        toggle_grab_cursor(&mut window);
+    }
 }
 
 pub struct UtilsPlugin;
@@ -37,7 +41,8 @@ pub struct UtilsPlugin;
 impl Plugin for UtilsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((FrameTimeDiagnosticsPlugin, WorldInspectorPlugin::new()))
-            .add_systems(Startup, initial_grab_cursor)
-            .add_systems(Update, (exit_on_escape, grab_cursor));
+            .add_systems(OnEnter(AppState::Playing), lock_cursor)
+            .add_systems(OnExit(AppState::Playing), release_cursor)
+            .add_systems(Update, grab_cursor.run_if(in_state(AppState::Playing)));
     }
 }